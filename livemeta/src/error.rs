@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 use xmp_toolkit::XmpError;
 
@@ -11,6 +13,21 @@ pub enum Error {
 
     #[error("missing field: {0}")]
     MissingField(&'static str),
+
+    #[error("invalid field: {0}")]
+    InvalidField(&'static str),
+
+    #[error("invalid path")]
+    InvalidPath,
+
+    #[error("written metadata did not match the document: {0}")]
+    Verification(PathBuf),
+
+    #[error("invalid tag {0:?}: {1}")]
+    InvalidTag(String, &'static str),
+
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
 }
 
 pub type Result<T = ()> = std::result::Result<T, Error>;
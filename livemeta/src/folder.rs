@@ -1,10 +1,18 @@
-use std::ffi::OsStr;
-use std::fs;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use xmp_toolkit::{FromStrOptions, ToStringOptions, XmpDateTime, XmpMeta, XmpValue, xmp_ns};
+use xmp_toolkit::{
+    FromStrOptions, IterOptions, ToStringOptions, XmpDateTime, XmpMeta, XmpValue, xmp_ns,
+};
 
 use crate::error::{Error, Result};
+use crate::item::{Item, UnknownField};
 
 const ABLETON_NS: &str = "https://ns.ableton.com/xmp/fs-resources/1.0/";
 
@@ -40,6 +48,11 @@ pub fn is_folder_metadata(path: &Path) -> bool {
 #[derive(Debug)]
 pub struct FolderMetadata {
     xmp: XmpMeta,
+    items: Vec<Item>,
+    loaded_items: Vec<Item>,
+    index: HashMap<String, usize>,
+    index_stale: bool,
+    lent_item: Option<(usize, String)>,
     dirty: bool,
 }
 
@@ -51,8 +64,16 @@ impl FolderMetadata {
 
     /// Reads a document from a `&str`.
     pub fn from_xmp_str(data: &str) -> Result<FolderMetadata> {
+        let xmp = XmpMeta::from_str_with_options(data, FromStrOptions::default())?;
+        let items = read_items(&xmp)?;
+
         Ok(FolderMetadata {
-            xmp: XmpMeta::from_str_with_options(data, FromStrOptions::default())?,
+            xmp,
+            index: build_index(&items),
+            index_stale: false,
+            lent_item: None,
+            loaded_items: items.clone(),
+            items,
             dirty: false,
         })
     }
@@ -66,12 +87,19 @@ impl FolderMetadata {
 
     /// Returns whether the document has changed since it was loaded.
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        self.dirty || self.items != self.loaded_items
     }
 
     /// Outputs the document as XML.
     pub fn to_xml(&self) -> Result<String> {
-        let xml = self.xmp.to_string_with_options(
+        let mut xmp = self.xmp.clone();
+
+        // Untouched items are left exactly as they were loaded.
+        if self.items != self.loaded_items {
+            write_items(&mut xmp, &self.items)?;
+        }
+
+        let xml = xmp.to_string_with_options(
             ToStringOptions::default()
                 .omit_packet_wrapper()
                 .set_indent_string("    ".into()),
@@ -80,6 +108,29 @@ impl FolderMetadata {
         Ok(xml)
     }
 
+    /// Writes the document to a `.xmp` file.
+    ///
+    /// To avoid leaving a truncated file behind if something goes wrong, the
+    /// document is first written to a temporary file alongside the destination,
+    /// which is flushed to disk and read back to make sure it contains the whole
+    /// document and parses to the same items. Only then is it moved into place,
+    /// replacing the existing file.
+    pub fn save(&self, path: &Path) -> Result {
+        let xml = self.to_xml()?;
+
+        write_atomically(path, &xml, &self.items)
+    }
+
+    /// Writes the XML of a `.xmp` file to disk exactly as it is, e.g. to restore the
+    /// contents it had before it was changed.
+    ///
+    /// The XML must be a valid document, and is written in the same way as [`save`](Self::save).
+    pub fn restore_xmp_file(path: &Path, xml: &str) -> Result {
+        let doc = FolderMetadata::from_xmp_str(xml)?;
+
+        write_atomically(path, xml, &doc.items)
+    }
+
     /// Sets the 'CreatorTool' property on the document.
     pub fn set_creator_tool(&mut self, value: impl Into<String>) -> Result {
         self.xmp
@@ -118,93 +169,444 @@ impl FolderMetadata {
 
     /// Returns the number of items (aka tagged files) in the document.
     pub fn item_count(&self) -> usize {
-        self.xmp.array_len(ABLETON_NS, "items")
+        self.items.len()
+    }
+
+    /// Returns an iterator over the items in the document.
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter()
+    }
+
+    /// Returns a mutable iterator over the items in the document.
+    ///
+    /// Any changes made to the items will mark the document as dirty.
+    pub fn items_mut(&mut self) -> impl Iterator<Item = &mut Item> {
+        // File paths may be changed via the iterator, so the index can no
+        // longer be trusted.
+        self.index_stale = true;
+
+        self.items.iter_mut()
+    }
+
+    /// Adds an item to the end of the document.
+    pub fn push_item(&mut self, item: Item) {
+        self.sync_index();
+
+        self.index
+            .entry(item.file_path.clone())
+            .or_insert(self.items.len());
+
+        self.items.push(item);
+    }
+
+    /// Finds the item for a given file.
+    pub fn find_item(&self, filename: &str) -> Option<&Item> {
+        if !self.is_index_current() {
+            return self.items.iter().find(|item| item.file_path == filename);
+        }
+
+        self.index.get(filename).map(|&i| &self.items[i])
+    }
+
+    /// Finds the item for a given file, allowing it to be modified.
+    pub fn find_item_mut(&mut self, filename: &str) -> Option<&mut Item> {
+        self.sync_index();
+
+        let i = *self.index.get(filename)?;
+
+        Some(self.lend_item(i))
+    }
+
+    /// Finds the item for a given file, adding an empty one to the end of the
+    /// document if it does not exist yet.
+    pub fn get_or_insert_item(&mut self, filename: &str) -> &mut Item {
+        self.sync_index();
+
+        let i = match self.index.get(filename) {
+            Some(&i) => i,
+            None => {
+                self.push_item(Item::new(filename));
+                self.items.len() - 1
+            }
+        };
+
+        self.lend_item(i)
+    }
+
+    /// Deletes the item for a given file from the document, returning it.
+    ///
+    /// If there are multiple items for the file, only the first is deleted.
+    pub fn delete_item(&mut self, filename: &str) -> Option<Item> {
+        self.sync_index();
+
+        let i = *self.index.get(filename)?;
+
+        // Every item after the deleted one shifts position.
+        self.index_stale = true;
+
+        Some(self.items.remove(i))
+    }
+
+    /// Hands out mutable access to an item, remembering its file path so that
+    /// the index can be fixed up if the caller renames it.
+    fn lend_item(&mut self, i: usize) -> &mut Item {
+        let item = &mut self.items[i];
+
+        self.lent_item = Some((i, item.file_path.clone()));
+
+        item
+    }
+
+    /// Returns whether the index reflects the current file paths of the items.
+    fn is_index_current(&self) -> bool {
+        !self.index_stale
+            && self
+                .lent_item
+                .as_ref()
+                .is_none_or(|(i, path)| self.items[*i].file_path == *path)
     }
 
-    /// Reads the filename of an item in the document.
+    /// Rebuilds the index if it might be out of date.
+    fn sync_index(&mut self) {
+        if !self.is_index_current() {
+            self.index = build_index(&self.items);
+            self.index_stale = false;
+        }
+
+        self.lent_item = None;
+    }
+}
+
+/// Accessors from before items could be read and written directly. These are kept
+/// so that existing code still compiles, and will be removed in a future version.
+#[allow(deprecated)]
+impl FolderMetadata {
+    /// Gets the filename of an item in the document.
+    #[deprecated(note = "use `items` or `find_item` and read `Item::file_path` instead")]
     pub fn get_filename(&self, item: &ItemSelector) -> Result<String> {
-        self.xmp
-            .property(ABLETON_NS, &item.filename)
-            .map(|v| v.value)
-            .ok_or(Error::MissingField(""))
+        self.items
+            .get(item.index)
+            .map(|item| item.file_path.clone())
+            .ok_or(Error::MissingField("filePath"))
     }
 
     /// Sets the filename of an item in the document.
+    #[deprecated(note = "use `items_mut` or `find_item_mut` and set `Item::file_path` instead")]
     pub fn set_filename(&mut self, item: &ItemSelector, value: impl Into<String>) -> Result {
-        self.xmp
-            .set_property(ABLETON_NS, &item.filename, &XmpValue::new(value.into()))?;
-
-        self.dirty = true;
+        self.item_at_mut(item)?.file_path = value.into();
 
         Ok(())
     }
 
     /// Returns the number of keywords (aka tags) for an item in the document.
+    #[deprecated(note = "use `items` or `find_item` and read `Item::keywords` instead")]
     pub fn keyword_count(&self, item: &ItemSelector) -> usize {
-        self.xmp.array_len(ABLETON_NS, &item.keywords.value)
+        self.items
+            .get(item.index)
+            .map_or(0, |item| item.keywords.len())
     }
 
     /// Reads a keyword from an item in the document.
+    #[deprecated(note = "use `items` or `find_item` and read `Item::keywords` instead")]
     pub fn get_keyword(&self, item: &ItemSelector, i: usize) -> Result<String> {
-        self.xmp
-            .array_item(ABLETON_NS, &item.keywords.value, i as i32)
-            .map(|v| v.value)
-            .ok_or(Error::MissingField(""))
+        self.items
+            .get(item.index)
+            .and_then(|item| item.keywords.get(i))
+            .cloned()
+            .ok_or(Error::MissingField("keywords"))
     }
 
     /// Adds a keyword to an item in the document.
+    #[deprecated(
+        note = "use `find_item_mut` or `get_or_insert_item` and `Item::add_keyword` instead"
+    )]
     pub fn push_keyword(&mut self, item: &ItemSelector, value: impl Into<String>) -> Result {
-        self.xmp
-            .append_array_item(ABLETON_NS, &item.keywords, &XmpValue::new(value.into()))?;
-
-        self.dirty = true;
+        self.item_at_mut(item)?.keywords.push(value.into());
 
         Ok(())
     }
 
     /// Deletes a keyword from an item in the document.
+    #[deprecated(note = "use `find_item_mut` and `Item::remove_keyword` instead")]
     pub fn delete_keyword(&mut self, item: &ItemSelector, i: usize) -> Result {
-        self.xmp
-            .delete_array_item(ABLETON_NS, &item.keywords.value, i as i32)?;
+        let keywords = &mut self.item_at_mut(item)?.keywords;
 
-        self.dirty = true;
+        if i < keywords.len() {
+            keywords.remove(i);
+        }
 
         Ok(())
     }
 
     /// Deletes all keywords from an item in the document.
+    #[deprecated(note = "use `find_item_mut` and clear `Item::keywords` instead")]
     pub fn delete_keywords(&mut self, item: &ItemSelector) -> Result {
-        self.xmp.delete_property(ABLETON_NS, &item.keywords.value)?;
-
-        self.dirty = true;
+        self.item_at_mut(item)?.keywords.clear();
 
         Ok(())
     }
+
+    fn item_at_mut(&mut self, item: &ItemSelector) -> Result<&mut Item> {
+        if item.index >= self.items.len() {
+            return Err(Error::MissingField("items"));
+        }
+
+        self.sync_index();
+
+        Ok(self.lend_item(item.index))
+    }
 }
 
-/// Paths for an individual item in a metadata document.
+/// The position of an individual item in a metadata document.
+#[deprecated(note = "use `FolderMetadata::items` or `FolderMetadata::find_item` instead")]
 pub struct ItemSelector {
-    filename: String,
-    keywords: XmpValue<String>,
+    index: usize,
 }
 
+#[allow(deprecated)]
 impl ItemSelector {
-    /// Creates a new item selector for an item in a documents.
+    /// Creates a new item selector for an item in a document.
     ///
     /// This does not validate that the item actually exists!
     pub fn new(i: usize) -> Result<ItemSelector> {
+        Ok(ItemSelector { index: i })
+    }
+}
+
+/// Maps each file path to the position of its first item.
+fn build_index(items: &[Item]) -> HashMap<String, usize> {
+    let mut index = HashMap::with_capacity(items.len());
+
+    for (i, item) in items.iter().enumerate() {
+        index.entry(item.file_path.clone()).or_insert(i);
+    }
+
+    index
+}
+
+/// Writes XML to a temporary file alongside the destination and checks that it
+/// contains the expected items, then moves it into place.
+fn write_atomically(path: &Path, xml: &str, items: &[Item]) -> Result {
+    let folder = path.parent().ok_or(Error::InvalidPath)?;
+    let filename = path.file_name().ok_or(Error::InvalidPath)?;
+
+    fs::create_dir_all(folder)?;
+
+    let temp_path = folder.join(temp_name(filename));
+
+    let result =
+        write_verified(&temp_path, xml, items).and_then(|_| Ok(fs::rename(&temp_path, path)?));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result?;
+
+    sync_folder(folder)?;
+
+    Ok(())
+}
+
+/// Returns a unique name for a temporary file to write a document to.
+///
+/// The name includes the time and a counter as well as the process ID, so that a
+/// temporary file left behind by a crash can't block a later process with the same ID.
+fn temp_name(filename: &OsStr) -> OsString {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let mut name = OsString::from(".");
+    name.push(filename);
+    name.push(format!(
+        ".{}-{}-{}.tmp",
+        process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    name
+}
+
+/// Writes XML to a new file and flushes it to disk, then checks that the whole
+/// document was written and can be read back in without losing any items.
+fn write_verified(path: &Path, xml: &str, items: &[Item]) -> Result {
+    let mut file = File::create_new(path)?;
+
+    file.write_all(xml.as_bytes())?;
+    file.sync_all()?;
+
+    drop(file);
+
+    if fs::read_to_string(path)? != xml {
+        return Err(Error::Verification(path.to_path_buf()));
+    }
+
+    let written = FolderMetadata::from_xmp_file(path)?;
+
+    if written.items != items {
+        return Err(Error::Verification(path.to_path_buf()));
+    }
+
+    Ok(())
+}
+
+/// Flushes a folder's entries to disk, so that a rename within it is durable.
+#[cfg(unix)]
+fn sync_folder(folder: &Path) -> Result {
+    File::open(folder)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Windows does not support opening folders as files, and `MoveFileEx` is
+/// already durable enough for our purposes.
+#[cfg(not(unix))]
+fn sync_folder(_folder: &Path) -> Result {
+    Ok(())
+}
+
+/// Reads all of the items from a document.
+fn read_items(xmp: &XmpMeta) -> Result<Vec<Item>> {
+    let count = xmp.array_len(ABLETON_NS, "items");
+    let mut items = Vec::with_capacity(count);
+
+    for i in 1..=count {
         let item_path = XmpMeta::compose_array_item_path(ABLETON_NS, "items", i as i32)?;
 
-        let filename =
-            XmpMeta::compose_struct_field_path(ABLETON_NS, &item_path, ABLETON_NS, "filePath")?;
+        let mut file_path = None;
+        let mut item = Item::default();
+
+        let mut iter = xmp.iter(IterOptions::default().property(ABLETON_NS, &item_path));
+
+        // The first node visited is the item itself, which tells us how the
+        // document spells the path to it. Items are written back as a whole array,
+        // so skipping one we can't read would delete it on the next save.
+        let Some(root) = iter.next() else {
+            return Err(Error::InvalidField("items"));
+        };
+
+        let prefix = format!("{}/", root.name);
+
+        for prop in iter {
+            let Some(path) = prop.name.strip_prefix(&prefix) else {
+                continue;
+            };
+
+            match field_name(path) {
+                Some(("filePath", None)) => file_path = Some(prop.value.value),
+                Some(("keywords", None)) | Some(("colors", None)) => {}
+                Some(("keywords", Some(_))) => item.keywords.push(prop.value.value),
+                Some(("colors", Some(_))) => item.colors.push(
+                    prop.value
+                        .value
+                        .parse()
+                        .map_err(|_| Error::InvalidField("colors"))?,
+                ),
+                _ => item.unknown_fields.push(UnknownField {
+                    path: path.to_string(),
+                    value: prop.value,
+                }),
+            }
+        }
+
+        item.file_path = file_path.ok_or(Error::MissingField("filePath"))?;
+
+        items.push(item);
+    }
 
-        let keywords =
-            XmpMeta::compose_struct_field_path(ABLETON_NS, &item_path, ABLETON_NS, "keywords")?;
+    Ok(items)
+}
 
-        Ok(ItemSelector {
-            filename,
-            keywords: XmpValue::new(keywords).set_is_array(true),
-        })
+/// Replaces all of the items in a document.
+fn write_items(xmp: &mut XmpMeta, items: &[Item]) -> Result {
+    xmp.delete_property(ABLETON_NS, "items")?;
+    xmp.set_property(ABLETON_NS, "items", &XmpValue::default().set_is_array(true))?;
+
+    for (i, item) in items.iter().enumerate() {
+        let item_path = XmpMeta::compose_array_item_path(ABLETON_NS, "items", i as i32 + 1)?;
+
+        xmp.set_property(
+            ABLETON_NS,
+            &item_path,
+            &XmpValue::default().set_is_struct(true),
+        )?;
+
+        xmp.set_struct_field(
+            ABLETON_NS,
+            &item_path,
+            ABLETON_NS,
+            "filePath",
+            &XmpValue::new(item.file_path.clone()),
+        )?;
+
+        write_array(
+            xmp,
+            &item_path,
+            "colors",
+            item.colors.iter().map(u32::to_string),
+        )?;
+        write_array(xmp, &item_path, "keywords", item.keywords.iter().cloned())?;
+
+        for field in &item.unknown_fields {
+            xmp.set_property(
+                ABLETON_NS,
+                &format!("{}/{}", item_path, field.path),
+                &field.value,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes an array field to an item, omitting it if there are no values.
+fn write_array(
+    xmp: &mut XmpMeta,
+    item_path: &str,
+    name: &str,
+    values: impl ExactSizeIterator<Item = String>,
+) -> Result {
+    if values.len() == 0 {
+        return Ok(());
+    }
+
+    let array_path = XmpMeta::compose_struct_field_path(ABLETON_NS, item_path, ABLETON_NS, name)?;
+    let array = XmpValue::new(array_path).set_is_array(true);
+
+    for value in values {
+        xmp.append_array_item(ABLETON_NS, &array, &XmpValue::new(value))?;
+    }
+
+    Ok(())
+}
+
+/// Splits a path relative to an item into the name of the top-level Ableton
+/// field, and whether it points at an element within that field.
+///
+/// Returns `None` if the path points at anything else.
+fn field_name(path: &str) -> Option<(&str, Option<&str>)> {
+    let (name, rest) = match path.find(['/', '[']) {
+        Some(i) => path.split_at(i),
+        None => (path, ""),
+    };
+
+    let (ns_prefix, name) = name.split_once(':')?;
+
+    if XmpMeta::namespace_uri(ns_prefix).as_deref() != Some(ABLETON_NS) {
+        return None;
+    }
+
+    match rest {
+        "" => Some((name, None)),
+        index if index.starts_with('[') && index.ends_with(']') && !index.contains('/') => {
+            Some((name, Some(index)))
+        }
+        _ => None,
     }
 }
 
@@ -223,4 +625,202 @@ mod tests {
     fn should_ignore_non_metadata_file() {
         assert!(!is_folder_metadata(Path::new("C:/foo/sound.wav")));
     }
+
+    #[test]
+    fn should_read_items() -> Result {
+        let meta = FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        let items: Vec<&Item> = meta.items().collect();
+
+        assert_eq!(items.len(), 2);
+
+        assert_eq!(items[0].file_path, "bd1.wav");
+        assert_eq!(items[0].keywords, ["Drums|Kick", "Creator|17cupsofcoffee"]);
+        assert_eq!(items[0].colors, [1]);
+
+        assert_eq!(items[1].file_path, "bd2.wav");
+        assert_eq!(items[1].keywords, ["Creator|17cupsofcoffee"]);
+        assert!(items[1].colors.is_empty());
+
+        assert!(!meta.is_dirty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_preserve_unknown_fields() -> Result {
+        let data = include_str!("../../src/test_data/initial.xml").replace(
+            "<ablFR:filePath>bd2.wav</ablFR:filePath>",
+            "<ablFR:filePath>bd2.wav</ablFR:filePath><ablFR:rating>5</ablFR:rating>",
+        );
+
+        let mut meta = FolderMetadata::from_xmp_str(&data)?;
+
+        for item in meta.items_mut() {
+            item.keywords.push("CustomTag".into());
+        }
+
+        assert!(meta.is_dirty());
+
+        let meta = FolderMetadata::from_xmp_str(&meta.to_xml()?)?;
+        let item = meta.items().nth(1).unwrap();
+
+        assert_eq!(item.keywords, ["Creator|17cupsofcoffee", "CustomTag"]);
+        assert_eq!(item.unknown_fields.len(), 1);
+        assert_eq!(item.unknown_fields[0].path, "ablFR:rating");
+        assert_eq!(item.unknown_fields[0].value.value, "5");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_unreadable_items() {
+        let data = include_str!("../../src/test_data/initial.xml").replace(
+            "<rdf:Bag>\n                    <rdf:li rdf:parseType=\"Resource\">",
+            "<rdf:Bag>\n                    <rdf:li>bd0.wav</rdf:li>\n                    <rdf:li rdf:parseType=\"Resource\">",
+        );
+
+        assert!(data.contains("bd0.wav"));
+        assert!(FolderMetadata::from_xmp_str(&data).is_err());
+    }
+
+    #[test]
+    fn should_find_items_by_filename() -> Result {
+        let mut meta =
+            FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        assert_eq!(meta.find_item("bd2.wav").unwrap().keywords.len(), 1);
+        assert!(meta.find_item("bd3.wav").is_none());
+
+        meta.get_or_insert_item("bd3.wav").add_keyword("Drums|Kick");
+        meta.get_or_insert_item("bd3.wav").add_keyword("CustomTag");
+
+        assert_eq!(meta.item_count(), 3);
+        assert_eq!(
+            meta.find_item("bd3.wav").unwrap().keywords,
+            ["Drums|Kick", "CustomTag"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_track_renamed_items() -> Result {
+        let mut meta =
+            FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        meta.find_item_mut("bd1.wav").unwrap().file_path = "kick.wav".into();
+
+        assert!(meta.find_item("bd1.wav").is_none());
+        assert!(meta.find_item_mut("bd1.wav").is_none());
+        assert_eq!(meta.find_item("kick.wav").unwrap().colors, [1]);
+
+        for item in meta.items_mut() {
+            item.file_path = item.file_path.replace(".wav", ".aif");
+        }
+
+        assert!(meta.find_item_mut("kick.wav").is_none());
+        assert!(meta.find_item_mut("kick.aif").is_some());
+        assert!(meta.find_item("bd2.aif").is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_delete_items() -> Result {
+        let mut meta =
+            FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        let item = meta.delete_item("bd1.wav").unwrap();
+
+        assert_eq!(item.colors, [1]);
+        assert!(meta.delete_item("bd1.wav").is_none());
+
+        assert_eq!(meta.item_count(), 1);
+        assert_eq!(meta.find_item("bd2.wav").unwrap().keywords.len(), 1);
+        assert!(meta.is_dirty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_save_documents() -> Result {
+        let folder = std::env::temp_dir().join(format!("livemeta-save-{}", process::id()));
+        let path = get_folder_metadata_path(&folder);
+
+        let mut meta =
+            FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        meta.get_or_insert_item("bd3.wav").add_keyword("Drums|Kick");
+        meta.save(&path)?;
+
+        meta.find_item_mut("bd3.wav")
+            .unwrap()
+            .add_keyword("CustomTag");
+        meta.save(&path)?;
+
+        let saved = FolderMetadata::from_xmp_file(&path)?;
+
+        assert_eq!(
+            saved.find_item("bd3.wav").unwrap().keywords,
+            ["Drums|Kick", "CustomTag"]
+        );
+
+        // Only the saved document should be left behind.
+        assert_eq!(fs::read_dir(path.parent().unwrap())?.count(), 1);
+
+        fs::remove_dir_all(&folder)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_ignore_stale_temp_files() -> Result {
+        let folder = std::env::temp_dir().join(format!("livemeta-stale-{}", process::id()));
+        let path = get_folder_metadata_path(&folder);
+
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        // A temporary file left behind by a crashed process with the same ID.
+        let mut stale = OsString::from(".");
+        stale.push(path.file_name().unwrap());
+        stale.push(format!(".{}.tmp", process::id()));
+        fs::write(path.with_file_name(stale), "")?;
+
+        let meta = FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+        let result = meta.save(&path);
+
+        fs::remove_dir_all(&folder)?;
+
+        result
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn should_support_deprecated_accessors() -> Result {
+        let mut meta =
+            FolderMetadata::from_xmp_str(include_str!("../../src/test_data/initial.xml"))?;
+
+        let item = ItemSelector::new(0)?;
+
+        assert_eq!(meta.get_filename(&item)?, "bd1.wav");
+        assert_eq!(meta.keyword_count(&item), 2);
+        assert_eq!(meta.get_keyword(&item, 0)?, "Drums|Kick");
+
+        meta.push_keyword(&item, "Loop")?;
+        meta.delete_keyword(&item, 0)?;
+        meta.set_filename(&item, "kick.wav")?;
+
+        let kick = meta.find_item("kick.wav").unwrap();
+
+        assert_eq!(kick.keywords, ["Creator|17cupsofcoffee", "Loop"]);
+        assert!(meta.is_dirty());
+
+        meta.delete_keywords(&item)?;
+
+        assert_eq!(meta.keyword_count(&item), 0);
+        assert!(meta.get_filename(&ItemSelector::new(10)?).is_err());
+
+        Ok(())
+    }
 }
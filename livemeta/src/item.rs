@@ -0,0 +1,143 @@
+use xmp_toolkit::XmpValue;
+
+/// An individual file's entry in a folder's metadata document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Item {
+    /// The path of the file, relative to the folder.
+    pub file_path: String,
+
+    /// The keywords (aka tags) applied to the file.
+    pub keywords: Vec<String>,
+
+    /// The color labels applied to the file.
+    pub colors: Vec<u32>,
+
+    /// Any other fields stored for the file.
+    ///
+    /// LiveTagger doesn't know what these mean, but they are preserved when
+    /// the document is written back out.
+    pub unknown_fields: Vec<UnknownField>,
+}
+
+impl Item {
+    /// Creates a new item with no keywords or colors.
+    pub fn new(file_path: impl Into<String>) -> Item {
+        Item {
+            file_path: file_path.into(),
+            ..Item::default()
+        }
+    }
+
+    /// Returns whether the item has a given keyword.
+    pub fn has_keyword(&self, keyword: &str) -> bool {
+        self.keywords.iter().any(|k| k == keyword)
+    }
+
+    /// Adds a keyword to the item, if it is not already present.
+    ///
+    /// Returns whether the keyword was added.
+    pub fn add_keyword(&mut self, keyword: impl Into<String>) -> bool {
+        let keyword = keyword.into();
+
+        if self.has_keyword(&keyword) {
+            return false;
+        }
+
+        self.keywords.push(keyword);
+
+        true
+    }
+
+    /// Removes a keyword from the item.
+    ///
+    /// Returns whether the keyword was removed.
+    pub fn remove_keyword(&mut self, keyword: &str) -> bool {
+        let len = self.keywords.len();
+
+        self.keywords.retain(|k| k != keyword);
+
+        self.keywords.len() != len
+    }
+
+    /// Returns whether the item has a given color label.
+    pub fn has_color(&self, color: u32) -> bool {
+        self.colors.contains(&color)
+    }
+
+    /// Adds a color label to the item, if it is not already present.
+    ///
+    /// Returns whether the color was added.
+    pub fn add_color(&mut self, color: u32) -> bool {
+        if self.has_color(color) {
+            return false;
+        }
+
+        self.colors.push(color);
+
+        true
+    }
+
+    /// Removes a color label from the item.
+    ///
+    /// Returns whether the color was removed.
+    pub fn remove_color(&mut self, color: u32) -> bool {
+        let len = self.colors.len();
+
+        self.colors.retain(|c| *c != color);
+
+        self.colors.len() != len
+    }
+}
+
+/// A field of an item which is not directly supported by LiveTagger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownField {
+    /// The XMP path of the field, relative to the item.
+    pub path: String,
+
+    /// The raw value of the field.
+    pub value: XmpValue<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_not_duplicate_keywords() {
+        let mut item = Item::new("bd1.wav");
+
+        assert!(item.add_keyword("Drums|Kick"));
+        assert!(!item.add_keyword("Drums|Kick"));
+
+        assert_eq!(item.keywords, ["Drums|Kick"]);
+    }
+
+    #[test]
+    fn should_remove_keywords() {
+        let mut item = Item::new("bd1.wav");
+
+        item.add_keyword("Drums|Kick");
+
+        assert!(item.remove_keyword("Drums|Kick"));
+        assert!(!item.remove_keyword("Drums|Kick"));
+
+        assert!(item.keywords.is_empty());
+    }
+
+    #[test]
+    fn should_not_duplicate_colors() {
+        let mut item = Item::new("bd1.wav");
+
+        assert!(item.add_color(1));
+        assert!(!item.add_color(1));
+        assert!(item.add_color(3));
+
+        assert_eq!(item.colors, [1, 3]);
+
+        assert!(item.remove_color(1));
+        assert!(!item.remove_color(1));
+
+        assert_eq!(item.colors, [3]);
+    }
+}
@@ -1,10 +1,16 @@
 mod error;
 mod folder;
+mod item;
+mod query;
 mod sample;
+mod tag;
 
 pub use error::*;
 pub use folder::*;
+pub use item::*;
+pub use query::*;
 pub use sample::*;
+pub use tag::*;
 
 use std::path::Path;
 
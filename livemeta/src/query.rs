@@ -0,0 +1,434 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::item::Item;
+use crate::tag::TAG_SEPARATOR;
+
+/// An expression for selecting items based on their keywords.
+///
+/// The syntax supports:
+///
+/// * Tags, which match an item with that tag or any tag nested within it. For
+///   example, `Drums` matches `Drums`, `Drums|Kick` and `Drums|Kick|Acoustic`.
+/// * Tags prefixed with `=`, which only match that exact tag.
+/// * Wildcards within tags - `*` matches any sequence of characters, and `?`
+///   matches a single character.
+/// * Double quotes around tags that contain spaces or special characters.
+/// * `AND`, `OR` and `NOT` operators, and parentheses for grouping. Terms that are
+///   next to each other without an operator are combined with `AND`.
+///
+/// Matching is case-insensitive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    /// Matches items with a keyword that matches the pattern.
+    Term(TagPattern),
+
+    /// Matches items that do not match the inner query.
+    Not(Box<Query>),
+
+    /// Matches items that match all of the inner queries.
+    And(Vec<Query>),
+
+    /// Matches items that match any of the inner queries.
+    Or(Vec<Query>),
+}
+
+/// A pattern that matches against a single keyword.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagPattern {
+    pattern: String,
+    exact: bool,
+}
+
+impl Query {
+    /// Parses a query expression.
+    pub fn parse(input: &str) -> Result<Query> {
+        let tokens = tokenize(input)?;
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let query = parser.parse_or()?;
+
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(Error::InvalidQuery(format!("unexpected {token}")));
+        }
+
+        Ok(query)
+    }
+
+    /// Returns whether a set of keywords matches the query.
+    pub fn matches<S: AsRef<str>>(&self, keywords: &[S]) -> bool {
+        match self {
+            Query::Term(pattern) => keywords.iter().any(|k| pattern.matches(k.as_ref())),
+            Query::Not(inner) => !inner.matches(keywords),
+            Query::And(inner) => inner.iter().all(|q| q.matches(keywords)),
+            Query::Or(inner) => inner.iter().any(|q| q.matches(keywords)),
+        }
+    }
+
+    /// Returns whether an item's keywords match the query.
+    pub fn matches_item(&self, item: &Item) -> bool {
+        self.matches(&item.keywords)
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Query> {
+        Query::parse(s)
+    }
+}
+
+impl TagPattern {
+    /// Creates a pattern from a tag, which may contain wildcards.
+    fn new(tag: &str, exact: bool) -> Result<TagPattern> {
+        let segments: Vec<String> = tag
+            .split(TAG_SEPARATOR)
+            .map(|segment| segment.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+
+        if segments.iter().any(String::is_empty) {
+            return Err(Error::InvalidQuery(format!(
+                "{tag:?} has an empty tag level"
+            )));
+        }
+
+        Ok(TagPattern {
+            pattern: segments.join(&TAG_SEPARATOR.to_string()).to_lowercase(),
+            exact,
+        })
+    }
+
+    /// Returns whether a keyword matches the pattern.
+    ///
+    /// Unless the pattern is exact, a keyword also matches if any of its parents do.
+    pub fn matches(&self, keyword: &str) -> bool {
+        let keyword = keyword.to_lowercase();
+
+        if wildcard_match(&self.pattern, &keyword) {
+            return true;
+        }
+
+        if self.exact {
+            return false;
+        }
+
+        keyword
+            .match_indices(TAG_SEPARATOR)
+            .any(|(i, _)| wildcard_match(&self.pattern, &keyword[..i]))
+    }
+}
+
+impl Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.exact {
+            write!(f, "={:?}", self.pattern)
+        } else {
+            write!(f, "{:?}", self.pattern)
+        }
+    }
+}
+
+/// Matches a string against a pattern containing `*` and `?` wildcards.
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+
+            _ => match backtrack {
+                // Let the last `*` swallow one more character, then try again.
+                Some((star_p, star_v)) => {
+                    p = star_p + 1;
+                    v = star_v + 1;
+                    backtrack = Some((star_p, star_v + 1));
+                }
+
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Tag { value: String, exact: bool },
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::And => f.write_str("AND"),
+            Token::Or => f.write_str("OR"),
+            Token::Not => f.write_str("NOT"),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::Tag { value, .. } => write!(f, "{value:?}"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+
+            _ => {
+                let exact = c == '=';
+
+                if exact {
+                    chars.next();
+                }
+
+                let value = if chars.peek() == Some(&'"') {
+                    chars.next();
+
+                    let mut value = String::new();
+
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => value.push(c),
+                            None => {
+                                return Err(Error::InvalidQuery("unclosed quote".into()));
+                            }
+                        }
+                    }
+
+                    value
+                } else {
+                    let mut value = String::new();
+
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                            break;
+                        }
+
+                        value.push(c);
+                        chars.next();
+                    }
+
+                    match value.as_str() {
+                        "AND" if !exact => {
+                            tokens.push(Token::And);
+                            continue;
+                        }
+
+                        "OR" if !exact => {
+                            tokens.push(Token::Or);
+                            continue;
+                        }
+
+                        "NOT" if !exact => {
+                            tokens.push(Token::Not);
+                            continue;
+                        }
+
+                        _ => value,
+                    }
+                };
+
+                tokens.push(Token::Tag { value, exact });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut terms = vec![self.parse_and()?];
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            terms.push(self.parse_and()?);
+        }
+
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Query::Or(terms)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut terms = vec![self.parse_not()?];
+
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                }
+
+                // Terms next to each other are implicitly combined with AND.
+                Some(Token::Not | Token::Open | Token::Tag { .. }) => {}
+
+                _ => break,
+            }
+
+            terms.push(self.parse_not()?);
+        }
+
+        Ok(if terms.len() == 1 {
+            terms.remove(0)
+        } else {
+            Query::And(terms)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<Query> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Query::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> Result<Query> {
+        match self.next() {
+            Some(Token::Open) => {
+                let query = self.parse_or()?;
+
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(Error::InvalidQuery("missing ')'".into())),
+                }
+            }
+
+            Some(Token::Tag { value, exact }) => Ok(Query::Term(TagPattern::new(&value, exact)?)),
+
+            Some(token) => Err(Error::InvalidQuery(format!("unexpected {token}"))),
+
+            None => Err(Error::InvalidQuery("unexpected end of query".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, keywords: &[&str]) -> bool {
+        Query::parse(query).unwrap().matches(keywords)
+    }
+
+    #[test]
+    fn should_match_hierarchy_prefixes() {
+        assert!(matches("Drums", &["Drums|Kick|Acoustic"]));
+        assert!(matches("Drums|Kick", &["Drums|Kick"]));
+        assert!(matches("drums|kick", &["Drums|Kick"]));
+        assert!(!matches("Drums|Ki", &["Drums|Kick"]));
+        assert!(!matches("Drums|Kick", &["Drums"]));
+    }
+
+    #[test]
+    fn should_match_exact_tags() {
+        assert!(matches("=Drums|Kick", &["Drums|Kick"]));
+        assert!(!matches("=Drums", &["Drums|Kick"]));
+    }
+
+    #[test]
+    fn should_match_wildcards() {
+        assert!(matches("Creator|17cups*", &["Creator|17cupsofcoffee"]));
+        assert!(matches("*|Kick", &["Drums|Kick|Acoustic"]));
+        assert!(matches("Drums|?ick", &["Drums|Kick"]));
+        assert!(!matches("Drums|?ick", &["Drums|Snare"]));
+    }
+
+    #[test]
+    fn should_match_quoted_tags() {
+        assert!(matches("\"Drums|Hi Hat\"", &["Drums|Hi Hat|Closed"]));
+        assert!(matches("=\"Drums | Hi  Hat\"", &["Drums|Hi Hat"]));
+    }
+
+    #[test]
+    fn should_combine_terms() {
+        let keywords = ["Drums|Kick", "Creator|17cupsofcoffee"];
+
+        assert!(matches("Drums|Kick AND Creator", &keywords));
+        assert!(matches("Drums|Kick Creator", &keywords));
+        assert!(!matches("Drums|Kick AND Loop", &keywords));
+        assert!(matches("Loop OR Drums|Kick", &keywords));
+        assert!(matches("Drums|Kick NOT Loop", &keywords));
+        assert!(!matches("NOT Drums", &keywords));
+        assert!(matches("NOT (Loop OR Drums|Snare)", &keywords));
+        assert!(matches("(Loop OR Drums) AND NOT Drums|Snare", &keywords));
+        assert!(matches("NOT Drums", &[] as &[&str]));
+    }
+
+    #[test]
+    fn should_bind_and_tighter_than_or() {
+        assert_eq!(
+            Query::parse("A OR B AND C").unwrap(),
+            Query::parse("A OR (B AND C)").unwrap()
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_queries() {
+        for query in [
+            "",
+            "(Drums",
+            "Drums)",
+            "Drums AND",
+            "OR Drums",
+            "\"Drums",
+            "Drums||Kick",
+        ] {
+            assert!(Query::parse(query).is_err(), "{query:?} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// The character Live uses to separate the levels of a tag's hierarchy.
+pub const TAG_SEPARATOR: char = '|';
+
+/// A validated tag, in Live's `Category|Tag|Sub Tag` format.
+///
+/// When parsed, whitespace at the start and end of each level is removed, and
+/// runs of whitespace within a level are collapsed to a single space.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tag(String);
+
+impl Tag {
+    /// Parses and normalizes a tag.
+    pub fn parse(value: &str) -> Result<Tag> {
+        let mut tag = String::with_capacity(value.len());
+
+        for (i, segment) in value.split(TAG_SEPARATOR).enumerate() {
+            if segment
+                .chars()
+                .any(|c| c.is_control() && !c.is_whitespace())
+            {
+                return Err(Error::InvalidTag(
+                    value.to_string(),
+                    "tags cannot contain control characters",
+                ));
+            }
+
+            let mut words = segment.split_whitespace();
+
+            let Some(first) = words.next() else {
+                return Err(Error::InvalidTag(
+                    value.to_string(),
+                    "tags cannot have empty levels",
+                ));
+            };
+
+            if i > 0 {
+                tag.push(TAG_SEPARATOR);
+            }
+
+            tag.push_str(first);
+
+            for word in words {
+                tag.push(' ');
+                tag.push_str(word);
+            }
+        }
+
+        Ok(Tag(tag))
+    }
+
+    /// Returns the tag as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns an iterator over the levels of the tag, starting with the category.
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split(TAG_SEPARATOR)
+    }
+
+    /// Returns the number of levels in the tag.
+    pub fn depth(&self) -> usize {
+        self.segments().count()
+    }
+
+    /// Returns the tag one level above this one, if there is one.
+    pub fn parent(&self) -> Option<Tag> {
+        self.0
+            .rsplit_once(TAG_SEPARATOR)
+            .map(|(parent, _)| Tag(parent.to_string()))
+    }
+
+    /// Returns the chain of parent tags, starting with the closest.
+    ///
+    /// For example, the parents of `Drums|Kick|Acoustic` are `Drums|Kick`
+    /// and `Drums`.
+    pub fn parents(&self) -> impl Iterator<Item = Tag> {
+        std::iter::successors(self.parent(), Tag::parent)
+    }
+
+    /// Returns whether this tag is the same as, or nested within, another tag.
+    pub fn starts_with(&self, prefix: &Tag) -> bool {
+        match self.0.strip_prefix(prefix.as_str()) {
+            Some(rest) => rest.is_empty() || rest.starts_with(TAG_SEPARATOR),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for Tag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Tag> {
+        Tag::parse(s)
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Tag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> String {
+        tag.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_tags() {
+        assert_eq!(Tag::parse("Drums").unwrap().as_str(), "Drums");
+        assert_eq!(
+            Tag::parse("Drums|HiHat|Closed").unwrap().as_str(),
+            "Drums|HiHat|Closed"
+        );
+    }
+
+    #[test]
+    fn should_normalize_whitespace() {
+        assert_eq!(
+            Tag::parse("  Drums | Hi   Hat|Closed ").unwrap().as_str(),
+            "Drums|Hi Hat|Closed"
+        );
+
+        assert_eq!(Tag::parse("Hi\tHat").unwrap().as_str(), "Hi Hat");
+    }
+
+    #[test]
+    fn should_reject_malformed_tags() {
+        for tag in [
+            "",
+            " ",
+            "Drums||Kick",
+            "|Drums",
+            "Drums|",
+            "Drums| |Kick",
+            "Drums\0Kick",
+        ] {
+            assert!(Tag::parse(tag).is_err(), "{tag:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn should_return_parents() {
+        let tag = Tag::parse("Drums|Kick|Acoustic").unwrap();
+
+        assert_eq!(tag.depth(), 3);
+        assert_eq!(tag.parent().unwrap().as_str(), "Drums|Kick");
+
+        let parents: Vec<Tag> = tag.parents().collect();
+
+        assert_eq!(
+            parents,
+            [
+                Tag::parse("Drums|Kick").unwrap(),
+                Tag::parse("Drums").unwrap()
+            ]
+        );
+
+        assert!(Tag::parse("Drums").unwrap().parent().is_none());
+    }
+
+    #[test]
+    fn should_match_prefixes() {
+        let tag = Tag::parse("Drums|Kick|Acoustic").unwrap();
+
+        assert!(tag.starts_with(&Tag::parse("Drums").unwrap()));
+        assert!(tag.starts_with(&Tag::parse("Drums|Kick").unwrap()));
+        assert!(tag.starts_with(&tag));
+        assert!(!tag.starts_with(&Tag::parse("Drums|Ki").unwrap()));
+        assert!(!tag.starts_with(&Tag::parse("Synth").unwrap()));
+    }
+}
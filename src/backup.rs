@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use livemeta::{FolderMetadata, Item};
+use tracing::{info, warn};
+
+use crate::changes::ChangeSet;
+use crate::journal;
+use crate::{SaveArgs, new_timestamp_id, process_folders};
+
+/// The ID given to backups made by older versions of LiveTagger, which were not timestamped.
+const LEGACY_ID: &str = "legacy";
+
+/// A backup of a folder's metadata.
+#[derive(Debug)]
+pub struct Backup {
+    pub id: String,
+    pub path: PathBuf,
+}
+
+/// Copies a metadata document to a new timestamped backup, then deletes the oldest
+/// backups so that at most `keep` remain.
+pub fn create_backup(xmp_path: &Path, keep: NonZeroUsize) -> anyhow::Result<PathBuf> {
+    let id = new_timestamp_id();
+
+    let mut path = backup_path(xmp_path, &id);
+    let mut suffix = 1;
+
+    while path.exists() {
+        path = backup_path(xmp_path, &format!("{id}-{suffix}"));
+        suffix += 1;
+    }
+
+    // The existing file is copied rather than moved, so that Live never sees
+    // the metadata disappear.
+    fs::copy(xmp_path, &path)?;
+
+    for old in list_backups(xmp_path)?
+        .into_iter()
+        .filter(|b| b.id != LEGACY_ID)
+        .skip(keep.get())
+    {
+        fs::remove_file(&old.path)?;
+        info!("Removed old backup {}", old.path.display());
+    }
+
+    Ok(path)
+}
+
+/// Lists the backups of a metadata document, newest first.
+pub fn list_backups(xmp_path: &Path) -> anyhow::Result<Vec<Backup>> {
+    let (Some(folder), Some(filename)) = (
+        xmp_path.parent(),
+        xmp_path.file_name().and_then(OsStr::to_str),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+
+        let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+
+        let Some(rest) = name.strip_prefix(filename) else {
+            continue;
+        };
+
+        let id = if rest == ".bak" {
+            LEGACY_ID
+        } else if let Some(id) = rest.strip_prefix('.').and_then(|r| r.strip_suffix(".bak")) {
+            id
+        } else {
+            continue;
+        };
+
+        backups.push(Backup {
+            id: id.to_string(),
+            path,
+        });
+    }
+
+    // Legacy backups are always older than timestamped ones.
+    backups.sort_by(|a, b| {
+        (a.id != LEGACY_ID, &a.id)
+            .cmp(&(b.id != LEGACY_ID, &b.id))
+            .reverse()
+    });
+
+    Ok(backups)
+}
+
+/// Logs the backups available for each folder.
+pub fn log_backups(folders: &[PathBuf]) -> anyhow::Result<()> {
+    for folder in folders {
+        let backups = list_backups(&livemeta::get_folder_metadata_path(folder))?;
+
+        info!("Backups for {}:", folder.display());
+
+        for backup in backups {
+            info!("    {}", backup.id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Restores the metadata of each folder from a backup.
+///
+/// The restore is recorded in the journal like any other run, so it can be undone.
+/// If no ID is provided, the most recent backup will be restored.
+pub fn restore_backups(
+    folders: &[PathBuf],
+    id: Option<&str>,
+    save: &SaveArgs,
+) -> anyhow::Result<()> {
+    if save.plan_out.is_some() {
+        bail!("Restores can't be written to a plan file");
+    }
+
+    let folders = folders
+        .iter()
+        .map(|folder| (folder.clone(), HashSet::new()))
+        .collect();
+
+    process_folders(save, folders, |folder, doc, _| {
+        let xmp_path = livemeta::get_folder_metadata_path(folder);
+        let backups = list_backups(&xmp_path)?;
+
+        let chosen = match id {
+            Some(id) => backups.iter().find(|b| b.id == id),
+            None => backups.first(),
+        };
+
+        let Some(chosen) = chosen else {
+            warn!("No matching backup found for {}", folder.display());
+            return Ok(ChangeSet::default());
+        };
+
+        info!("Restoring {} from backup {}", folder.display(), chosen.id);
+
+        let backup = FolderMetadata::from_xmp_file(&chosen.path)
+            .with_context(|| format!("Invalid backup: {}", chosen.path.display()))?;
+
+        // Every entry is replaced with the backup's, in the same order.
+        let before: Vec<Item> = doc.items().cloned().collect();
+
+        for item in &before {
+            doc.delete_item(&item.file_path);
+        }
+
+        for item in backup.items() {
+            doc.push_item(item.clone());
+        }
+
+        Ok(ChangeSet {
+            items: journal::diff_items(&before, doc),
+        })
+    })?;
+
+    Ok(())
+}
+
+/// Returns the path of a metadata document's backup with the given ID.
+fn backup_path(xmp_path: &Path, id: &str) -> PathBuf {
+    let mut path = xmp_path.as_os_str().to_owned();
+    path.push(format!(".{id}.bak"));
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_rotate_backups() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let xmp_path = dir.path().join("folder.xmp");
+
+        fs::write(&xmp_path, "v1")?;
+        fs::write(dir.path().join("folder.xmp.bak"), "v0")?;
+
+        let keep = NonZeroUsize::new(2).unwrap();
+        let mut created = Vec::new();
+
+        for version in ["v2", "v3", "v4"] {
+            created.push(create_backup(&xmp_path, keep)?);
+            fs::write(&xmp_path, version)?;
+        }
+
+        let backups = list_backups(&xmp_path)?;
+
+        // The two newest timestamped backups are kept, and legacy backups are
+        // never rotated out.
+        assert_eq!(
+            backups.iter().map(|b| &b.path).collect::<Vec<_>>(),
+            [&created[2], &created[1], &dir.path().join("folder.xmp.bak")]
+        );
+        assert_eq!(backups[2].id, LEGACY_ID);
+        assert_eq!(fs::read_to_string(&backups[0].path)?, "v3");
+        assert_eq!(fs::read_to_string(&backups[2].path)?, "v0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_restore_backups() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let folder = dir.path().to_path_buf();
+        let xmp_path = livemeta::get_folder_metadata_path(&folder);
+
+        let initial = FolderMetadata::from_xmp_str(include_str!("test_data/initial.xml"))?;
+        initial.save(&xmp_path)?;
+
+        create_backup(&xmp_path, NonZeroUsize::new(10).unwrap())?;
+
+        let mut changed = FolderMetadata::from_xmp_file(&xmp_path)?;
+        changed.delete_item("bd1.wav");
+        changed.save(&xmp_path)?;
+
+        restore_backups(&[folder], None, &SaveArgs::committed())?;
+
+        let restored = FolderMetadata::from_xmp_file(&xmp_path)?;
+
+        assert_eq!(
+            restored.items().collect::<Vec<_>>(),
+            initial.items().collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+}
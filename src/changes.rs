@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::SaveArgs;
+
+/// The formats that a run's changes can be reported in.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// Log messages describing each change as it is made.
+    #[default]
+    Text,
+
+    /// A JSON report of every change on standard output, written once the run has
+    /// finished. Log messages are written to standard error instead.
+    Json,
+}
+
+/// The changes made to a single item's keywords and colors.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ItemChanges {
+    pub file: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords_added: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords_removed: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors_added: Vec<u32>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub colors_removed: Vec<u32>,
+
+    /// Whether the item's entry was removed from the metadata entirely.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub entry_removed: bool,
+}
+
+impl ItemChanges {
+    pub fn is_empty(&self) -> bool {
+        self.keywords_added.is_empty()
+            && self.keywords_removed.is_empty()
+            && self.colors_added.is_empty()
+            && self.colors_removed.is_empty()
+            && !self.entry_removed
+    }
+
+    /// Returns the changes that would reverse these ones, with the added and
+    /// removed keywords and colors swapped.
+    pub fn inverted(&self) -> ItemChanges {
+        ItemChanges {
+            file: self.file.clone(),
+            keywords_added: self.keywords_removed.clone(),
+            keywords_removed: self.keywords_added.clone(),
+            colors_added: self.colors_removed.clone(),
+            colors_removed: self.colors_added.clone(),
+            entry_removed: false,
+        }
+    }
+}
+
+/// Returns the values in `a` which are not in `b`.
+pub fn difference<T: Clone + PartialEq>(a: &[T], b: &[T]) -> Vec<T> {
+    a.iter().filter(|v| !b.contains(v)).cloned().collect()
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// The changes that an operation made to a folder's metadata document.
+#[derive(Debug, Default, PartialEq)]
+pub struct ChangeSet {
+    pub items: Vec<ItemChanges>,
+}
+
+impl ChangeSet {
+    /// Records the changes made to an item, unless nothing actually changed.
+    pub fn push(&mut self, changes: ItemChanges) {
+        if !changes.is_empty() {
+            self.items.push(changes);
+        }
+    }
+
+    /// Returns the number of items that were changed.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// A report of the changes made by a run, for machine-readable output.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub folders: Vec<FolderReport>,
+    pub summary: Summary,
+}
+
+/// The changes made to a single folder.
+#[derive(Debug, Serialize)]
+pub struct FolderReport {
+    pub folder: PathBuf,
+
+    /// Whether the folder's metadata was written to disk.
+    pub saved: bool,
+
+    pub items: Vec<ItemChanges>,
+}
+
+/// The totals for a whole run.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub committed: bool,
+    pub run_id: Option<String>,
+    pub folders_processed: usize,
+    pub folders_changed: usize,
+    pub items_changed: usize,
+    pub keywords_added: usize,
+    pub keywords_removed: usize,
+    pub colors_added: usize,
+    pub colors_removed: usize,
+    pub entries_removed: usize,
+}
+
+impl Report {
+    /// Adds a folder's changes to the report.
+    pub fn add_folder(&mut self, folder: &Path, changes: ChangeSet, saved: bool) {
+        let summary = &mut self.summary;
+
+        summary.folders_processed += 1;
+
+        if !changes.is_empty() {
+            summary.folders_changed += 1;
+        }
+
+        for item in &changes.items {
+            summary.items_changed += 1;
+            summary.keywords_added += item.keywords_added.len();
+            summary.keywords_removed += item.keywords_removed.len();
+            summary.colors_added += item.colors_added.len();
+            summary.colors_removed += item.colors_removed.len();
+            summary.entries_removed += usize::from(item.entry_removed);
+        }
+
+        self.folders.push(FolderReport {
+            folder: folder.to_path_buf(),
+            saved,
+            items: changes.items,
+        });
+    }
+
+    /// Prints the report to standard output, if JSON output was requested.
+    pub fn output(mut self, args: &SaveArgs, run_id: Option<String>) -> anyhow::Result<()> {
+        if args.output != OutputFormat::Json {
+            return Ok(());
+        }
+
+        self.folders.sort_by(|a, b| a.folder.cmp(&b.folder));
+        self.summary.committed = args.commit;
+        self.summary.run_id = run_id;
+
+        println!("{}", serde_json::to_string_pretty(&self)?);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_summarize_changes() -> anyhow::Result<()> {
+        let mut report = Report::default();
+
+        let mut changes = ChangeSet::default();
+
+        changes.push(ItemChanges {
+            file: "bd1.wav".into(),
+            keywords_added: vec!["Drums|Kick".into(), "Loop".into()],
+            colors_removed: vec![1],
+            ..ItemChanges::default()
+        });
+
+        changes.push(ItemChanges {
+            file: "bd2.wav".into(),
+            ..ItemChanges::default()
+        });
+
+        changes.push(ItemChanges {
+            file: "bd3.wav".into(),
+            entry_removed: true,
+            ..ItemChanges::default()
+        });
+
+        report.add_folder(Path::new("Drums"), changes, true);
+        report.add_folder(Path::new("Keys"), ChangeSet::default(), false);
+
+        let expected = r#"{
+  "folders": [
+    {
+      "folder": "Drums",
+      "saved": true,
+      "items": [
+        {
+          "file": "bd1.wav",
+          "keywords_added": [
+            "Drums|Kick",
+            "Loop"
+          ],
+          "colors_removed": [
+            1
+          ]
+        },
+        {
+          "file": "bd3.wav",
+          "entry_removed": true
+        }
+      ]
+    },
+    {
+      "folder": "Keys",
+      "saved": false,
+      "items": []
+    }
+  ],
+  "summary": {
+    "committed": false,
+    "run_id": null,
+    "folders_processed": 2,
+    "folders_changed": 1,
+    "items_changed": 2,
+    "keywords_added": 2,
+    "keywords_removed": 0,
+    "colors_added": 0,
+    "colors_removed": 1,
+    "entries_removed": 1
+  }
+}"#;
+
+        pretty_assertions::assert_eq!(serde_json::to_string_pretty(&report)?, expected);
+
+        Ok(())
+    }
+}
@@ -1,142 +1,378 @@
 use std::collections::HashSet;
 
-use livemeta::{FolderMetadata, ItemSelector};
+use livemeta::{FolderMetadata, Item, Query, TAG_SEPARATOR, Tag};
 use tracing::info;
 
+use crate::changes::{ChangeSet, ItemChanges, difference};
+
 /// Adds tags to the specified files.
 ///
-/// If an entry for a file does not exist yet in the metadata document, it will be added.
+/// `tags_for` is called with each filename, and returns the tags that file should
+/// be given. If an entry for a file does not exist yet in the metadata document, it
+/// will be added, as long as there are tags to add to it.
 pub fn add_tags(
     doc: &mut FolderMetadata,
-    mut files: HashSet<String>,
-    tags: &[String],
-) -> anyhow::Result<()> {
-    let item_count = doc.item_count();
-
-    for i in 1..=item_count {
-        let mut tags_added = Vec::new();
+    files: HashSet<String>,
+    mut tags_for: impl FnMut(&str) -> Vec<Tag>,
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
 
-        let item = ItemSelector::new(i)?;
+    for file in sorted(files) {
+        let tags = tags_for(&file);
 
-        let filename = doc.get_filename(&item)?;
+        if tags.is_empty() {
+            continue;
+        }
 
-        if files.take(&filename).is_some() {
-            let keyword_count = doc.keyword_count(&item);
-            let mut keywords = HashSet::new();
+        let item = doc.get_or_insert_item(&file);
 
-            for i in 1..=keyword_count {
-                keywords.insert(doc.get_keyword(&item, i)?);
-            }
+        let tags_added: Vec<&str> = tags
+            .iter()
+            .filter(|tag| item.add_keyword(tag.as_str()))
+            .map(Tag::as_str)
+            .collect();
 
-            for tag in tags {
-                if !keywords.contains(tag) {
-                    doc.push_keyword(&item, tag.clone())?;
-                    tags_added.push(tag.as_str());
-                }
-            }
-
-            if !tags_added.is_empty() {
-                info!("Adding tags to {}: {}", filename, tags_added.join(", "));
-            }
+        if !tags_added.is_empty() {
+            info!("Adding tags to {}: {}", file, tags_added.join(", "));
         }
-    }
-
-    for (i, new_file) in files.into_iter().enumerate() {
-        let mut tags_added = Vec::new();
-
-        let item = ItemSelector::new(item_count + i + 1)?;
 
-        doc.set_filename(&item, new_file.clone())?;
+        changes.push(ItemChanges {
+            keywords_added: tags_added.into_iter().map(String::from).collect(),
+            file,
+            ..ItemChanges::default()
+        });
+    }
 
-        for tag in tags {
-            doc.push_keyword(&item, tag.clone())?;
-            tags_added.push(tag.as_str());
-        }
+    Ok(changes)
+}
 
-        if !tags_added.is_empty() {
-            info!("Adding tags to {}: {}", &new_file, tags_added.join(", "));
-        }
+/// Returns the keywords that an argument naming an existing tag should match.
+///
+/// This is the argument exactly as it was written, so that malformed keywords can
+/// still be cleaned up, plus its normalized form if it is a valid tag (so that
+/// `" Loop "` matches the `Loop` keyword that adding it would have created).
+pub fn existing_keywords(arg: &str) -> Vec<String> {
+    let mut keywords = vec![arg.to_string()];
+
+    if let Ok(tag) = Tag::parse(arg)
+        && tag.as_str() != arg
+    {
+        keywords.push(tag.to_string());
     }
 
-    Ok(())
+    keywords
 }
 
 /// Removes tags from the specified files.
 ///
+/// Each tag matches the keywords returned by [`existing_keywords`].
+///
 /// This will not remove the files themselves from the metadata document, even
-/// if all the keywords are gone - while keywords are currently the only
-/// metadata stored for each file, Ableton could potentially add
-/// additional data in future versions.
+/// if all the keywords are gone - Ableton stores other data for each file
+/// (such as color labels), and could potentially add more in future versions.
 pub fn remove_tags(
     doc: &mut FolderMetadata,
-    mut files: HashSet<String>,
+    files: HashSet<String>,
     tags: &[String],
-) -> anyhow::Result<()> {
-    let item_count = doc.item_count();
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
 
-    for i in 1..=item_count {
-        let mut tags_removed = Vec::new();
+    let keywords: Vec<String> = tags.iter().flat_map(|tag| existing_keywords(tag)).collect();
 
-        let item = ItemSelector::new(i)?;
+    for file in sorted(files) {
+        let Some(item) = doc.find_item_mut(&file) else {
+            continue;
+        };
 
-        let filename = doc.get_filename(&item)?;
+        let tags_removed: Vec<&str> = keywords
+            .iter()
+            .filter(|keyword| item.remove_keyword(keyword))
+            .map(String::as_str)
+            .collect();
 
-        if files.take(&filename).is_some() {
-            let keyword_count = doc.keyword_count(&item);
+        if !tags_removed.is_empty() {
+            info!("Removing tags from {}: {}", file, tags_removed.join(", "));
+        }
 
-            let mut deleted_count = 0;
+        changes.push(ItemChanges {
+            keywords_removed: tags_removed.into_iter().map(String::from).collect(),
+            file,
+            ..ItemChanges::default()
+        });
+    }
 
-            // We iterate in reverse to avoid invalidating the indices
-            // when elements get deleted.
-            for i in (1..=keyword_count).rev() {
-                let keyword = doc.get_keyword(&item, i)?;
+    Ok(changes)
+}
 
-                if tags.contains(&keyword) {
-                    doc.delete_keyword(&item, i)?;
-                    tags_removed.push(keyword);
+/// Removed all tags from the specified files.
+///
+/// This will not remove the files themselves from the metadata document -
+/// Ableton stores other data for each file (such as color labels), and could
+/// potentially add more in future versions.
+pub fn remove_all_tags(
+    doc: &mut FolderMetadata,
+    files: HashSet<String>,
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
 
-                    deleted_count += 1;
-                }
-            }
+    for file in sorted(files) {
+        if let Some(item) = doc.find_item_mut(&file) {
+            let keywords_removed = std::mem::take(&mut item.keywords);
 
-            if deleted_count == keyword_count {
-                doc.delete_keywords(&item)?;
-            }
+            info!("Removing all tags from {}", file);
+
+            changes.push(ItemChanges {
+                file,
+                keywords_removed,
+                ..ItemChanges::default()
+            });
+        }
+    }
+
+    Ok(changes)
+}
 
-            if !tags_removed.is_empty() {
-                info!(
-                    "Removing tags from {}: {}",
-                    &filename,
-                    tags_removed.join(", ")
-                );
+/// Sets the color labels on the specified files, replacing any existing colors.
+///
+/// Passing an empty list of colors clears the labels. If an entry for a file does
+/// not exist yet in the metadata document, it will only be added if there are
+/// colors to set.
+pub fn set_colors(
+    doc: &mut FolderMetadata,
+    files: HashSet<String>,
+    colors: &[u32],
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
+    let mut unique_colors = Vec::new();
+
+    for &color in colors {
+        if !unique_colors.contains(&color) {
+            unique_colors.push(color);
+        }
+    }
+
+    for file in sorted(files) {
+        let item = if unique_colors.is_empty() {
+            match doc.find_item_mut(&file) {
+                Some(item) => item,
+                None => continue,
             }
+        } else {
+            doc.get_or_insert_item(&file)
+        };
+
+        if item.colors != unique_colors {
+            let old_colors = std::mem::replace(&mut item.colors, unique_colors.clone());
+
+            log_colors(item);
+
+            changes.push(ItemChanges {
+                file,
+                colors_added: difference(&item.colors, &old_colors),
+                colors_removed: difference(&old_colors, &item.colors),
+                ..ItemChanges::default()
+            });
         }
     }
 
-    Ok(())
+    Ok(changes)
 }
 
-/// Removed all tags from the specified files.
+/// Removes the entries for any files that no longer exist.
 ///
-/// This will not remove the files themselves from the metadata document -
-/// while keywords are currently the only metadata stored for each file,
-/// Ableton could potentially add additional data in future versions.
-pub fn remove_all_tags(doc: &mut FolderMetadata, mut files: HashSet<String>) -> anyhow::Result<()> {
-    let item_count = doc.item_count();
+/// `exists` is called with each entry's file path, relative to the folder.
+pub fn prune_items(
+    doc: &mut FolderMetadata,
+    exists: impl Fn(&str) -> bool,
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
+
+    let orphans: Vec<String> = doc
+        .items()
+        .filter(|item| !exists(&item.file_path))
+        .map(|item| item.file_path.clone())
+        .collect();
+
+    for file in orphans {
+        if let Some(item) = doc.delete_item(&file) {
+            info!("Removing entry for missing file {}", file);
+
+            changes.push(ItemChanges {
+                file,
+                keywords_removed: item.keywords,
+                colors_removed: item.colors,
+                entry_removed: true,
+                ..ItemChanges::default()
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Renames a tag on every item in the document.
+///
+/// `old` matches the keywords returned by [`existing_keywords`]. If `descendants`
+/// is set, any tags nested within `old` are moved underneath `new` as well. Tags
+/// which the item already has are not duplicated.
+pub fn rename_tag(
+    doc: &mut FolderMetadata,
+    old: &str,
+    new: &Tag,
+    descendants: bool,
+) -> anyhow::Result<ChangeSet> {
+    let old_keywords = existing_keywords(old);
+
+    let changes = rewrite_keywords(doc, |keyword| {
+        if old_keywords.iter().any(|old| keyword == old) {
+            return Some(vec![new.to_string()]);
+        }
+
+        if !descendants {
+            return None;
+        }
+
+        old_keywords.iter().find_map(|old| {
+            keyword
+                .strip_prefix(old.as_str())
+                .filter(|rest| rest.starts_with(TAG_SEPARATOR))
+                .map(|rest| vec![format!("{new}{rest}")])
+        })
+    });
+
+    Ok(changes)
+}
+
+/// Replaces several tags with a single tag on every item in the document.
+///
+/// Each of `tags` matches the keywords returned by [`existing_keywords`].
+pub fn merge_tags(
+    doc: &mut FolderMetadata,
+    tags: &[String],
+    into: &Tag,
+) -> anyhow::Result<ChangeSet> {
+    let keywords: Vec<String> = tags.iter().flat_map(|tag| existing_keywords(tag)).collect();
+
+    let changes = rewrite_keywords(doc, |keyword| {
+        keywords
+            .iter()
+            .any(|k| k == keyword)
+            .then(|| vec![into.to_string()])
+    });
+
+    Ok(changes)
+}
+
+/// Replaces a tag with several tags on every item in the document.
+///
+/// `tag` matches the keywords returned by [`existing_keywords`].
+pub fn split_tag(doc: &mut FolderMetadata, tag: &str, into: &[Tag]) -> anyhow::Result<ChangeSet> {
+    let keywords = existing_keywords(tag);
+
+    let changes = rewrite_keywords(doc, |keyword| {
+        keywords
+            .iter()
+            .any(|k| k == keyword)
+            .then(|| into.iter().map(Tag::to_string).collect())
+    });
+
+    Ok(changes)
+}
+
+/// Replaces keywords on every item in the document.
+///
+/// `replace` is called with each keyword, and returns the keywords it should be
+/// replaced with, or `None` if it should be left alone. Replacements are inserted
+/// where the original keyword was, unless the item already has them.
+fn rewrite_keywords(
+    doc: &mut FolderMetadata,
+    mut replace: impl FnMut(&str) -> Option<Vec<String>>,
+) -> ChangeSet {
+    let mut changes = ChangeSet::default();
 
-    for i in 1..=item_count {
-        let item = ItemSelector::new(i)?;
+    for item in doc.items_mut() {
+        if !item.keywords.iter().any(|k| replace(k).is_some()) {
+            continue;
+        }
 
-        let filename = doc.get_filename(&item)?;
+        let old_keywords = std::mem::take(&mut item.keywords);
+        let mut replaced = Vec::new();
 
-        if files.take(&filename).is_some() {
-            doc.delete_keywords(&item)?;
+        for keyword in &old_keywords {
+            match replace(keyword) {
+                Some(replacements) => {
+                    replaced.push(format!("{} -> {}", keyword, replacements.join(", ")));
+
+                    for replacement in replacements {
+                        item.add_keyword(replacement);
+                    }
+                }
+
+                None => {
+                    item.add_keyword(keyword.as_str());
+                }
+            }
+        }
 
-            info!("Removing all tags from {}", &filename);
+        if item.keywords != old_keywords {
+            info!(
+                "Rewriting tags on {}: {}",
+                item.file_path,
+                replaced.join("; ")
+            );
+
+            changes.push(ItemChanges {
+                file: item.file_path.clone(),
+                keywords_added: difference(&item.keywords, &old_keywords),
+                keywords_removed: difference(&old_keywords, &item.keywords),
+                ..ItemChanges::default()
+            });
         }
     }
 
-    Ok(())
+    changes
+}
+
+fn log_colors(item: &Item) {
+    if item.colors.is_empty() {
+        info!("Clearing colors on {}", item.file_path);
+    } else {
+        let colors: Vec<String> = item.colors.iter().map(u32::to_string).collect();
+
+        info!(
+            "Setting colors on {}: {}",
+            item.file_path,
+            colors.join(", ")
+        );
+    }
+}
+
+/// Narrows down a set of files to those whose current tags match a query.
+///
+/// Files without an entry in the metadata document are treated as having no tags.
+pub fn filter_files(
+    doc: &FolderMetadata,
+    files: HashSet<String>,
+    query: Option<&Query>,
+) -> HashSet<String> {
+    let Some(query) = query else {
+        return files;
+    };
+
+    files
+        .into_iter()
+        .filter(|file| match doc.find_item(file) {
+            Some(item) => query.matches_item(item),
+            None => query.matches::<&str>(&[]),
+        })
+        .collect()
+}
+
+/// Sorts a set of filenames, so that they get processed in a consistent order.
+fn sorted(files: HashSet<String>) -> Vec<String> {
+    let mut files: Vec<String> = files.into_iter().collect();
+    files.sort();
+    files
 }
 
 #[cfg(test)]
@@ -156,7 +392,9 @@ mod tests {
         files.insert("bd2.wav".into());
         files.insert("bd3.wav".into());
 
-        add_tags(&mut meta, files, &["Drums|Kick".into(), "CustomTag".into()])?;
+        let tags = [Tag::parse("Drums|Kick")?, Tag::parse("CustomTag")?];
+
+        add_tags(&mut meta, files, |_| tags.to_vec())?;
 
         assert!(meta.is_dirty());
         pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
@@ -189,6 +427,54 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn should_remove_malformed_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let bd1 = meta.find_item_mut("bd1.wav").unwrap();
+        bd1.keywords.push("Drums||Kick|".into());
+
+        let changes = remove_tags(
+            &mut meta,
+            HashSet::from(["bd1.wav".to_string()]),
+            &["Drums||Kick|".into()],
+        )?;
+
+        assert_eq!(changes.len(), 1);
+        assert!(
+            !meta
+                .find_item("bd1.wav")
+                .unwrap()
+                .keywords
+                .contains(&"Drums||Kick|".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_remove_normalized_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let changes = remove_tags(
+            &mut meta,
+            HashSet::from(["bd1.wav".to_string()]),
+            &[" Drums |  Kick ".into()],
+        )?;
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            meta.find_item("bd1.wav").unwrap().keywords,
+            ["Creator|17cupsofcoffee"]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn should_remove_all_tags() -> anyhow::Result<()> {
         let initial = include_str!("test_data/initial.xml");
@@ -209,4 +495,296 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn should_set_colors() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/colors_set.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let mut files = HashSet::new();
+
+        files.insert("bd1.wav".into());
+        files.insert("bd2.wav".into());
+        files.insert("bd3.wav".into());
+
+        set_colors(&mut meta, files, &[2, 5])?;
+
+        assert!(meta.is_dirty());
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_clear_colors() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/colors_cleared.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let mut files = HashSet::new();
+
+        files.insert("bd1.wav".into());
+        files.insert("bd2.wav".into());
+        files.insert("bd3.wav".into());
+
+        set_colors(&mut meta, files, &[])?;
+
+        assert!(meta.is_dirty());
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_prune_items() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/items_pruned.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        prune_items(&mut meta, |file| file == "bd2.wav")?;
+
+        assert!(meta.is_dirty());
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_record_changes() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let mut files = HashSet::new();
+
+        files.insert("bd1.wav".into());
+        files.insert("bd3.wav".into());
+
+        let tags = [Tag::parse("Drums|Kick")?, Tag::parse("Loop")?];
+
+        let changes = add_tags(&mut meta, files, |_| tags.to_vec())?;
+
+        assert_eq!(
+            changes.items,
+            [
+                ItemChanges {
+                    file: "bd1.wav".into(),
+                    keywords_added: vec!["Loop".into()],
+                    ..ItemChanges::default()
+                },
+                ItemChanges {
+                    file: "bd3.wav".into(),
+                    keywords_added: vec!["Drums|Kick".into(), "Loop".into()],
+                    ..ItemChanges::default()
+                },
+            ]
+        );
+
+        let changes = prune_items(&mut meta, |file| file != "bd1.wav")?;
+
+        assert_eq!(
+            changes.items,
+            [ItemChanges {
+                file: "bd1.wav".into(),
+                keywords_removed: vec![
+                    "Drums|Kick".into(),
+                    "Creator|17cupsofcoffee".into(),
+                    "Loop".into()
+                ],
+                colors_removed: vec![1],
+                entry_removed: true,
+                ..ItemChanges::default()
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_filter_files() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let files: HashSet<String> = ["bd1.wav", "bd2.wav", "bd4.wav"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        let query = Query::parse("Creator NOT Drums|Kick")?;
+
+        assert_eq!(
+            sorted(filter_files(&meta, files.clone(), Some(&query))),
+            ["bd2.wav"]
+        );
+
+        let query = Query::parse("NOT Drums")?;
+
+        assert_eq!(
+            sorted(filter_files(&meta, files.clone(), Some(&query))),
+            ["bd2.wav", "bd4.wav"]
+        );
+
+        assert_eq!(filter_files(&meta, files.clone(), None), files);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_rename_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/tags_renamed.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        rename_tag(&mut meta, "Creator", &Tag::parse("Author")?, true)?;
+
+        assert!(meta.is_dirty());
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_only_rename_exact_tags_by_default() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        rename_tag(&mut meta, "Creator", &Tag::parse("Author")?, false)?;
+
+        assert!(!meta.is_dirty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_not_duplicate_renamed_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        meta.get_or_insert_item("bd1.wav")
+            .add_keyword("Drums|Kick|Acoustic");
+
+        rename_tag(
+            &mut meta,
+            "Drums|Kick|Acoustic",
+            &Tag::parse("Drums|Kick")?,
+            false,
+        )?;
+
+        assert_eq!(
+            meta.find_item("bd1.wav").unwrap().keywords,
+            ["Drums|Kick", "Creator|17cupsofcoffee"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_rename_malformed_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        meta.get_or_insert_item("bd1.wav")
+            .add_keyword("Drums||Kick|Acoustic");
+
+        let changes = rename_tag(&mut meta, "Drums||Kick", &Tag::parse("Drums|Kick")?, true)?;
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            meta.find_item("bd1.wav").unwrap().keywords,
+            [
+                "Drums|Kick",
+                "Creator|17cupsofcoffee",
+                "Drums|Kick|Acoustic"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_merge_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        meta.get_or_insert_item("bd1.wav")
+            .add_keyword("Creator|17cups");
+        meta.get_or_insert_item("bd3.wav")
+            .add_keyword("Creator|17cups");
+
+        let changes = merge_tags(
+            &mut meta,
+            &["Creator|17cups".into(), "Creator|17cupsofcoffee".into()],
+            &Tag::parse("Creator|17cupsofcoffee")?,
+        )?;
+
+        assert_eq!(changes.len(), 2);
+
+        assert_eq!(
+            meta.find_item("bd1.wav").unwrap().keywords,
+            ["Drums|Kick", "Creator|17cupsofcoffee"]
+        );
+        assert_eq!(
+            meta.find_item("bd2.wav").unwrap().keywords,
+            ["Creator|17cupsofcoffee"]
+        );
+        assert_eq!(
+            meta.find_item("bd3.wav").unwrap().keywords,
+            ["Creator|17cupsofcoffee"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_split_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/tags_split.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let changes = split_tag(
+            &mut meta,
+            "Drums|Kick",
+            &[Tag::parse("Drums")?, Tag::parse("Creator|17cupsofcoffee")?],
+        )?;
+
+        assert_eq!(changes.len(), 1);
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_split_malformed_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        meta.get_or_insert_item("bd1.wav")
+            .add_keyword("Drums||Kick");
+
+        let changes = split_tag(
+            &mut meta,
+            "Drums||Kick",
+            &[Tag::parse("Drums")?, Tag::parse("Loop")?],
+        )?;
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            meta.find_item("bd1.wav").unwrap().keywords,
+            ["Drums|Kick", "Creator|17cupsofcoffee", "Drums", "Loop"]
+        );
+
+        Ok(())
+    }
 }
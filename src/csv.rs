@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+/// Appends a row of fields to a CSV document.
+///
+/// Fields are quoted if they contain commas, quotes or line breaks, as per RFC 4180.
+pub fn write_row<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+
+        let field = field.as_ref();
+
+        if field.contains([',', '"', '\n', '\r']) {
+            let _ = write!(out, "\"{}\"", field.replace('"', "\"\""));
+        } else {
+            out.push_str(field);
+        }
+    }
+
+    out.push('\n');
+}
+
+/// Parses a CSV document into rows of fields.
+///
+/// Quoted fields may contain commas, escaped quotes and line breaks, as per RFC 4180.
+/// Blank lines are skipped.
+pub fn parse(data: &str) -> anyhow::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut chars = data.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+
+                    Some('"') => break,
+
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+
+                        field.push(c);
+                    }
+
+                    None => anyhow::bail!("Unclosed quote on line {}", line),
+                }
+            },
+
+            ',' => row.push(std::mem::take(&mut field)),
+
+            '\r' if chars.peek() == Some(&'\n') => {}
+
+            '\n' => {
+                line += 1;
+
+                if !row.is_empty() || !field.is_empty() {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+            }
+
+            c => field.push(c),
+        }
+    }
+
+    if !row.is_empty() || !field.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_quote_fields() {
+        let mut out = String::new();
+
+        write_row(&mut out, &["Drums|Kick", "a, b", "say \"hi\""]);
+
+        assert_eq!(out, "Drums|Kick,\"a, b\",\"say \"\"hi\"\"\"\n");
+    }
+
+    #[test]
+    fn should_parse_rows() -> anyhow::Result<()> {
+        let rows = parse("a,\"b, c\",\"say \"\"hi\"\"\"\r\n\n,x,\n\"multi\nline\"")?;
+
+        assert_eq!(
+            rows,
+            [
+                vec!["a", "b, c", "say \"hi\""],
+                vec!["", "x", ""],
+                vec!["multi\nline"],
+            ]
+        );
+
+        assert!(parse("\"unclosed").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use livemeta::FolderMetadata;
+use similar::TextDiff;
+
+use crate::SaveArgs;
+
+/// Renders a unified diff between the original contents of a metadata file and the
+/// contents that will be written to it.
+///
+/// Files which don't exist yet are shown as being created from `/dev/null`.
+pub fn unified_diff(path: &Path, original: Option<&str>, updated: &str) -> String {
+    let path = path.display().to_string();
+
+    let old_name = match original {
+        Some(_) => path.as_str(),
+        None => "/dev/null",
+    };
+
+    TextDiff::from_lines(original.unwrap_or_default(), updated)
+        .unified_diff()
+        .header(old_name, &path)
+        .to_string()
+}
+
+/// Prints a diff of the changes that will be written to a metadata file, if the
+/// user asked for one.
+pub fn print_diff(
+    args: &SaveArgs,
+    path: &Path,
+    original: Option<&str>,
+    xmp: &FolderMetadata,
+) -> anyhow::Result<()> {
+    if args.diff {
+        print!("{}", unified_diff(path, original, &xmp.to_xml()?));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_render_unified_diff() {
+        let original = "<a>\n<b>1</b>\n<c/>\n</a>\n";
+        let updated = "<a>\n<b>2</b>\n<c/>\n</a>\n";
+
+        assert_eq!(
+            unified_diff(Path::new("Drums/x.xmp"), Some(original), updated),
+            "\
+--- Drums/x.xmp
++++ Drums/x.xmp
+@@ -1,4 +1,4 @@
+ <a>
+-<b>1</b>
++<b>2</b>
+ <c/>
+ </a>
+"
+        );
+
+        assert_eq!(
+            unified_diff(Path::new("Drums/x.xmp"), None, "<a/>\n"),
+            "\
+--- /dev/null
++++ Drums/x.xmp
+@@ -0,0 +1 @@
++<a/>
+"
+        );
+    }
+}
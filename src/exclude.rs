@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use glob::{MatchOptions, Pattern};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// The name of the file listing paths to skip, in gitignore syntax.
+pub const IGNORE_FILE: &str = ".livetaggerignore";
+
+/// Decides which paths should be skipped when searching for samples, based on
+/// exclude globs and the patterns in an ignore file.
+#[derive(Debug)]
+pub struct Exclusions {
+    root: PathBuf,
+
+    /// The canonical path of the root folder, for matching absolute paths.
+    canonical_root: Option<PathBuf>,
+
+    globs: Vec<Pattern>,
+    ignore: Gitignore,
+}
+
+impl Exclusions {
+    /// Creates exclusions from a set of globs, plus the ignore file in the root
+    /// folder (if there is one).
+    pub fn load(root: &Path, excludes: &[String]) -> anyhow::Result<Exclusions> {
+        let path = root.join(IGNORE_FILE);
+
+        let ignore_file = if path.exists() {
+            fs::read_to_string(&path)
+                .with_context(|| format!("Could not read {}", path.display()))?
+        } else {
+            String::new()
+        };
+
+        Exclusions::new(root, excludes, &ignore_file)
+            .with_context(|| format!("Invalid exclusions for {}", root.display()))
+    }
+
+    /// Creates exclusions from a set of globs and the contents of an ignore file.
+    ///
+    /// Patterns in the ignore file are relative to the root folder.
+    pub fn new(root: &Path, excludes: &[String], ignore_file: &str) -> anyhow::Result<Exclusions> {
+        let globs = excludes
+            .iter()
+            .map(|glob| Pattern::new(glob).with_context(|| format!("Invalid exclude glob: {glob}")))
+            .collect::<anyhow::Result<_>>()?;
+
+        let mut builder = GitignoreBuilder::new(root);
+
+        for line in ignore_file.lines() {
+            builder
+                .add_line(None, line)
+                .with_context(|| format!("Invalid pattern in {IGNORE_FILE}: {line}"))?;
+        }
+
+        Ok(Exclusions {
+            root: root.to_path_buf(),
+            canonical_root: fs::canonicalize(root).ok(),
+            globs,
+            ignore: builder.build()?,
+        })
+    }
+
+    /// Returns whether a path should be skipped, either because it matches an
+    /// exclusion or because one of the folders containing it does.
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        // Exclude globs are matched the same way as include globs, where `*` doesn't
+        // match across folders.
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+
+        let excluded_by_glob = path
+            .ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| {
+                self.globs
+                    .iter()
+                    .any(|glob| glob.matches_path_with(p, options))
+            });
+
+        excluded_by_glob || self.is_ignored(path, is_dir)
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = path
+            .strip_prefix(&self.root)
+            .ok()
+            .or_else(|| {
+                let canonical_root = self.canonical_root.as_ref()?;
+                path.strip_prefix(canonical_root).ok()
+            })
+            .unwrap_or(path);
+
+        // Paths outside of the root can't be matched by the ignore file.
+        if relative.has_root() || relative.as_os_str().is_empty() {
+            return false;
+        }
+
+        self.ignore
+            .matched_path_or_any_parents(relative, is_dir)
+            .is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IGNORE: &str = "\
+# Folders that Live creates
+Ableton Project Info/
+Backup/
+
+*Demo*
+!Drum Demo.wav
+";
+
+    #[test]
+    fn should_exclude_paths() -> anyhow::Result<()> {
+        let exclusions = Exclusions::new(
+            Path::new("."),
+            &["**/Recorded".to_string(), "*.aif".to_string()],
+            IGNORE,
+        )?;
+
+        for path in [
+            "Backup/Kick.wav",
+            "Drums/Backup/Kick.wav",
+            "./Drums/Ableton Project Info/Kick.wav",
+            "Vendor/Demo Songs/Loop.wav",
+            "Vendor Demo.wav",
+            "Recorded/Take 1.wav",
+            "./Set/Recorded/Take 1.wav",
+            "Kick.aif",
+        ] {
+            assert!(
+                exclusions.is_excluded(Path::new(path), false),
+                "{path} should be excluded"
+            );
+        }
+
+        for path in [
+            "Kick.wav",
+            "Drums/Kick.wav",
+            "Drums/Drum Demo.wav",
+            "Backup.wav",
+            "Drums/Kick.aif",
+        ] {
+            assert!(
+                !exclusions.is_excluded(Path::new(path), false),
+                "{path} should not be excluded"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_ignore_absolute_paths() -> anyhow::Result<()> {
+        let exclusions = Exclusions::new(Path::new("."), &[], IGNORE)?;
+        let cwd = fs::canonicalize(".")?;
+
+        assert!(exclusions.is_excluded(&cwd.join("Backup/Kick.wav"), false));
+        assert!(!exclusions.is_excluded(&cwd.join("Drums/Kick.wav"), false));
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_invalid_exclusions() {
+        assert!(Exclusions::new(Path::new("."), &["[".to_string()], "").is_err());
+        assert!(Exclusions::new(Path::new("."), &[], "Drums/[z-a]").is_err());
+    }
+}
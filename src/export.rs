@@ -0,0 +1,146 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use livemeta::FolderMetadata;
+use serde::{Deserialize, Serialize};
+
+use crate::csv;
+
+/// The output formats supported when exporting tags.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ExportFormat {
+    /// CSV, with a header row.
+    #[default]
+    Csv,
+
+    /// A JSON array, with one object per record.
+    Json,
+}
+
+/// A single keyword or color applied to a file.
+///
+/// Files with no keywords or colors are exported as a single record with neither.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub folder: String,
+    pub file: String,
+
+    #[serde(default)]
+    pub keyword: Option<String>,
+
+    #[serde(default)]
+    pub color: Option<u32>,
+}
+
+/// Reads the records from a folder's metadata, relative to the root of the export.
+///
+/// Records are sorted by filename. Each file's keywords come first, in the order
+/// they are stored, followed by its colors.
+pub fn collect_records(root: &Path, folder: &Path, doc: &FolderMetadata) -> Vec<Record> {
+    let relative = folder.strip_prefix(root).unwrap_or(folder);
+
+    let folder = if relative.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        relative.display().to_string()
+    };
+
+    let mut items: Vec<_> = doc.items().collect();
+    items.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+
+    let mut records = Vec::new();
+
+    for item in items {
+        let record = |keyword, color| Record {
+            folder: folder.clone(),
+            file: item.file_path.clone(),
+            keyword,
+            color,
+        };
+
+        for keyword in &item.keywords {
+            records.push(record(Some(keyword.clone()), None));
+        }
+
+        for &color in &item.colors {
+            records.push(record(None, Some(color)));
+        }
+
+        if item.keywords.is_empty() && item.colors.is_empty() {
+            records.push(record(None, None));
+        }
+    }
+
+    records
+}
+
+/// Reads the records from the metadata of each folder, in the order the folders
+/// are given.
+pub fn collect_all_records(root: &Path, folders: &[PathBuf]) -> anyhow::Result<Vec<Record>> {
+    let mut records = Vec::new();
+
+    for folder in folders {
+        let doc = FolderMetadata::from_xmp_file(&livemeta::get_folder_metadata_path(folder))?;
+
+        records.extend(collect_records(root, folder, &doc));
+    }
+
+    Ok(records)
+}
+
+/// Renders a list of records in the given format.
+pub fn render(records: &[Record], format: ExportFormat) -> anyhow::Result<String> {
+    match format {
+        ExportFormat::Csv => Ok(render_csv(records)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(records)? + "\n"),
+    }
+}
+
+fn render_csv(records: &[Record]) -> String {
+    let mut out = String::new();
+
+    csv::write_row(&mut out, &["folder", "file", "keyword", "color"]);
+
+    for record in records {
+        csv::write_row(
+            &mut out,
+            &[
+                record.folder.as_str(),
+                record.file.as_str(),
+                record.keyword.as_deref().unwrap_or_default(),
+                &record.color.map(|c| c.to_string()).unwrap_or_default(),
+            ],
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_export_csv() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+        meta.get_or_insert_item("bd0.wav");
+
+        let root = Path::new("Samples");
+        let records = collect_records(root, &root.join("Drums"), &meta);
+
+        let expected = "\
+folder,file,keyword,color
+Drums,bd0.wav,,
+Drums,bd1.wav,Drums|Kick,
+Drums,bd1.wav,Creator|17cupsofcoffee,
+Drums,bd1.wav,,1
+Drums,bd2.wav,Creator|17cupsofcoffee,
+";
+
+        pretty_assertions::assert_eq!(render(&records, ExportFormat::Csv)?, expected);
+
+        Ok(())
+    }
+}
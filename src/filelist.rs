@@ -0,0 +1,112 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::canonicalize_folder;
+
+/// Reads a list of paths from a file, or from standard input if the path is `-`.
+pub fn read(source: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let data = if source == "-" {
+        let mut data = String::new();
+
+        io::stdin()
+            .read_to_string(&mut data)
+            .context("Could not read file list from standard input")?;
+
+        data
+    } else {
+        fs::read_to_string(source).with_context(|| format!("Could not read {source}"))?
+    };
+
+    Ok(parse(&data))
+}
+
+/// Parses a list of paths.
+///
+/// If the list contains any NUL characters (e.g. from `find -print0`), paths are
+/// separated by those. Otherwise, there is one path per line. Blank entries are skipped.
+pub fn parse(data: &str) -> Vec<PathBuf> {
+    let entries: Vec<&str> = if data.contains('\0') {
+        data.split('\0').collect()
+    } else {
+        data.lines().collect()
+    };
+
+    entries
+        .into_iter()
+        .filter(|entry| !entry.trim().is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Returns the folder containing a listed file.
+///
+/// Lists can mix relative and absolute paths (or spell the same folder in different
+/// ways, e.g. with `..`), so the folder is canonicalized to make sure that every file
+/// in it ends up in the same group.
+pub fn listed_folder(path: &Path) -> anyhow::Result<PathBuf> {
+    let parent = path.parent().unwrap_or(Path::new(""));
+
+    canonicalize_folder(parent).with_context(|| format!("Could not resolve {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::Component;
+
+    use super::*;
+
+    #[test]
+    fn should_parse_file_lists() {
+        assert_eq!(
+            parse("Drums/Kick 01.wav\r\n\n./Keys/Pad.wav\n"),
+            [
+                PathBuf::from("Drums/Kick 01.wav"),
+                PathBuf::from("./Keys/Pad.wav")
+            ]
+        );
+
+        assert_eq!(
+            parse("Drums/Kick\n01.wav\0Keys/Pad.wav\0"),
+            [
+                PathBuf::from("Drums/Kick\n01.wav"),
+                PathBuf::from("Keys/Pad.wav")
+            ]
+        );
+    }
+
+    #[test]
+    fn should_resolve_listed_folders() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let drums = fs::canonicalize(dir.path())?.join("Drums");
+        fs::create_dir(&drums)?;
+
+        // The same folder, relative to the current folder.
+        let depth = env::current_dir()?
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .count();
+
+        let mut relative: PathBuf = std::iter::repeat_n("..", depth).collect();
+        relative.extend(
+            drums
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_))),
+        );
+
+        let paths = [
+            drums.join("bd1.wav"),
+            relative.join("bd2.wav"),
+            drums.join("..").join("Drums").join("bd3.wav"),
+        ];
+
+        for path in &paths {
+            assert_eq!(listed_folder(path)?, drums);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use clap::ValueEnum;
+use livemeta::{FolderMetadata, Tag};
+use tracing::{info, warn};
+
+use crate::changes::{ChangeSet, ItemChanges};
+use crate::csv;
+use crate::export::Record;
+
+/// The input formats supported when importing tags. These match the formats
+/// written by `export`.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ImportFormat {
+    /// CSV, with a header row containing `file` and optionally `folder`, `keyword`
+    /// and `color` columns.
+    Csv,
+
+    /// A JSON array of objects with the same fields as the CSV columns.
+    Json,
+}
+
+impl ImportFormat {
+    /// Guesses the format of a file from its extension, defaulting to CSV.
+    pub fn from_path(path: &Path) -> ImportFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ImportFormat::Json,
+            _ => ImportFormat::Csv,
+        }
+    }
+}
+
+/// The keywords and colors imported for a single file.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportedTags {
+    pub keywords: Vec<Tag>,
+    pub colors: Vec<u32>,
+}
+
+/// Parses a list of records.
+pub fn parse_records(data: &str, format: ImportFormat) -> anyhow::Result<Vec<Record>> {
+    match format {
+        ImportFormat::Json => Ok(serde_json::from_str(data)?),
+        ImportFormat::Csv => parse_csv(data),
+    }
+}
+
+fn parse_csv(data: &str) -> anyhow::Result<Vec<Record>> {
+    let mut rows = csv::parse(data)?.into_iter();
+
+    let Some(header) = rows.next() else {
+        return Ok(Vec::new());
+    };
+
+    let column = |name: &str| header.iter().position(|h| h.trim() == name);
+
+    let Some(file_column) = column("file") else {
+        bail!("The header row must contain a 'file' column");
+    };
+
+    let folder_column = column("folder");
+    let keyword_column = column("keyword");
+    let color_column = column("color");
+
+    let mut records = Vec::new();
+
+    for (i, row) in rows.enumerate() {
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|c| row.get(c))
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+
+        let color = field(color_column)
+            .map(|c| c.parse())
+            .transpose()
+            .with_context(|| format!("Invalid color on row {}", i + 2))?;
+
+        records.push(Record {
+            folder: field(folder_column).unwrap_or(".").to_string(),
+            file: field(Some(file_column))
+                .with_context(|| format!("Missing file on row {}", i + 2))?
+                .to_string(),
+            keyword: field(keyword_column).map(String::from),
+            color,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Groups records by the folder they belong to, relative to the root of the import.
+///
+/// Every file mentioned is included, even if none of its records have a keyword
+/// or color.
+pub fn group_records(
+    root: &Path,
+    records: Vec<Record>,
+) -> anyhow::Result<HashMap<PathBuf, BTreeMap<String, ImportedTags>>> {
+    let mut folders: HashMap<PathBuf, BTreeMap<String, ImportedTags>> = HashMap::new();
+
+    for record in records {
+        // Files may also be given as a path relative to the folder.
+        let path = root.join(&record.folder).join(&record.file);
+
+        let (Some(folder), Some(file)) = (path.parent(), path.file_name().and_then(|f| f.to_str()))
+        else {
+            bail!("Invalid path: {}", path.display());
+        };
+
+        let tags = folders
+            .entry(folder.to_path_buf())
+            .or_default()
+            .entry(file.to_string())
+            .or_default();
+
+        if let Some(keyword) = &record.keyword {
+            let tag = Tag::parse(keyword)?;
+
+            if !tags.keywords.contains(&tag) {
+                tags.keywords.push(tag);
+            }
+        }
+
+        if let Some(color) = record.color {
+            if !(1..=7).contains(&color) {
+                bail!("Invalid color for {}: {}", path.display(), color);
+            }
+
+            if !tags.colors.contains(&color) {
+                tags.colors.push(color);
+            }
+        }
+    }
+
+    Ok(folders)
+}
+
+/// Removes the files that don't exist from a set of grouped records, logging a
+/// warning for each one.
+///
+/// Otherwise, a typo or an outdated path in an import would create metadata (and
+/// folders!) for samples that aren't there.
+pub fn skip_missing_files(folders: &mut HashMap<PathBuf, BTreeMap<String, ImportedTags>>) {
+    folders.retain(|folder, files| {
+        if !folder.is_dir() {
+            warn!(
+                "Skipping the files in {} as it doesn't exist",
+                folder.display()
+            );
+
+            return false;
+        }
+
+        files.retain(|file, _| {
+            let path = folder.join(file);
+            let exists = path.is_file();
+
+            if !exists {
+                warn!("Skipping {} as it doesn't exist", path.display());
+            }
+
+            exists
+        });
+
+        !files.is_empty()
+    });
+}
+
+/// Applies imported keywords and colors to the files in a metadata document.
+///
+/// If `replace` is set, each file's keywords and colors are replaced with the
+/// imported ones. Otherwise, they are added to what the file already has.
+pub fn apply_tags(
+    doc: &mut FolderMetadata,
+    files: &BTreeMap<String, ImportedTags>,
+    replace: bool,
+) -> anyhow::Result<ChangeSet> {
+    let mut changes = ChangeSet::default();
+
+    for (file, tags) in files {
+        let has_tags = !tags.keywords.is_empty() || !tags.colors.is_empty();
+
+        let item = if has_tags {
+            doc.get_or_insert_item(file)
+        } else {
+            match doc.find_item_mut(file) {
+                Some(item) => item,
+                None => continue,
+            }
+        };
+
+        let mut log = Vec::new();
+        let mut item_changes = ItemChanges {
+            file: file.clone(),
+            ..ItemChanges::default()
+        };
+
+        if replace {
+            let keywords: HashSet<&str> = tags.keywords.iter().map(Tag::as_str).collect();
+
+            item.keywords.retain(|keyword| {
+                let keep = keywords.contains(keyword.as_str());
+
+                if !keep {
+                    log.push(format!("-{keyword}"));
+                    item_changes.keywords_removed.push(keyword.clone());
+                }
+
+                keep
+            });
+
+            item.colors.retain(|color| {
+                let keep = tags.colors.contains(color);
+
+                if !keep {
+                    log.push(format!("-color {color}"));
+                    item_changes.colors_removed.push(*color);
+                }
+
+                keep
+            });
+        }
+
+        for keyword in &tags.keywords {
+            if item.add_keyword(keyword.as_str()) {
+                log.push(format!("+{keyword}"));
+                item_changes.keywords_added.push(keyword.to_string());
+            }
+        }
+
+        for &color in &tags.colors {
+            if item.add_color(color) {
+                log.push(format!("+color {color}"));
+                item_changes.colors_added.push(color);
+            }
+        }
+
+        if !log.is_empty() {
+            info!("Importing tags for {}: {}", file, log.join(", "));
+        }
+
+        changes.push(item_changes);
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn import(data: &str) -> anyhow::Result<BTreeMap<String, ImportedTags>> {
+        let records = parse_records(data, ImportFormat::Csv)?;
+        let mut folders = group_records(Path::new("Samples"), records)?;
+
+        Ok(folders
+            .remove(Path::new("Samples/Drums"))
+            .unwrap_or_default())
+    }
+
+    #[test]
+    fn should_group_records() -> anyhow::Result<()> {
+        let files = import(
+            "\
+folder,file,keyword,color
+Drums,bd1.wav,Drums|Kick,
+Drums,bd1.wav,,2
+Drums,bd2.wav,,
+.,Drums/bd3.wav,Drums|Kick,
+",
+        )?;
+
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            ["bd1.wav", "bd2.wav", "bd3.wav"]
+        );
+        assert_eq!(files["bd1.wav"].keywords, [Tag::parse("Drums|Kick")?]);
+        assert_eq!(files["bd1.wav"].colors, [2]);
+        assert_eq!(files["bd2.wav"], ImportedTags::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_invalid_records() {
+        assert!(import("folder,keyword\nDrums,Drums|Kick\n").is_err());
+        assert!(import("folder,file,keyword\nDrums,bd1.wav,Drums||Kick\n").is_err());
+        assert!(import("folder,file,color\nDrums,bd1.wav,8\n").is_err());
+        assert!(import("folder,file,color\nDrums,bd1.wav,red\n").is_err());
+    }
+
+    #[test]
+    fn should_skip_missing_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        fs::create_dir(dir.path().join("Drums"))?;
+        fs::write(dir.path().join("Drums/bd1.wav"), "")?;
+
+        let records = parse_records(
+            "\
+folder,file,keyword
+Drums,bd1.wav,Drums|Kick
+Drums,bd2.wav,Drums|Kick
+Drum,bd1.wav,Drums|Kick
+",
+            ImportFormat::Csv,
+        )?;
+
+        let mut folders = group_records(dir.path(), records)?;
+
+        skip_missing_files(&mut folders);
+
+        assert_eq!(folders.len(), 1);
+        assert_eq!(
+            folders[&dir.path().join("Drums")]
+                .keys()
+                .collect::<Vec<_>>(),
+            ["bd1.wav"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_add_imported_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let files = import("folder,file,keyword,color\nDrums,bd1.wav,Drums|Snare,2\n")?;
+
+        apply_tags(&mut meta, &files, false)?;
+
+        let bd1 = meta.find_item("bd1.wav").unwrap();
+
+        assert_eq!(
+            bd1.keywords,
+            ["Drums|Kick", "Creator|17cupsofcoffee", "Drums|Snare"]
+        );
+        assert_eq!(bd1.colors, [1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_replace_imported_tags() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+        let expected = include_str!("test_data/tags_imported.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let files = import(
+            "\
+folder,file,keyword,color
+Drums,bd1.wav,Creator|17cupsofcoffee,
+Drums,bd1.wav,Drums|Snare,
+Drums,bd2.wav,,
+",
+        )?;
+
+        apply_tags(&mut meta, &files, true)?;
+
+        assert!(meta.is_dirty());
+        pretty_assertions::assert_eq!(meta.to_xml().unwrap(), expected.replace("\r\n", "\n"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use livemeta::{FolderMetadata, Item};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::changes::{ChangeSet, ItemChanges, difference};
+use crate::{SaveArgs, new_timestamp_id, plan, process_folders};
+
+/// A record of the changes that a committed run made to folder metadata, which can
+/// be used to undo them later.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Journal {
+    pub id: String,
+
+    /// The arguments LiveTagger was run with.
+    pub command: Vec<String>,
+
+    /// The ID of the run that this run undid, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<String>,
+
+    /// Whether this run has since been undone.
+    #[serde(default)]
+    pub undone: bool,
+
+    pub folders: Vec<FolderChanges>,
+
+    /// Whether the journal has been written to the state directory yet.
+    #[serde(skip)]
+    created: bool,
+}
+
+/// The changes made to a single folder's metadata.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderChanges {
+    pub folder: PathBuf,
+    pub items: Vec<ItemChanges>,
+}
+
+impl Journal {
+    /// Creates an empty journal for the current run.
+    pub fn new() -> Journal {
+        Journal {
+            id: new_timestamp_id(),
+            command: env::args().skip(1).collect(),
+            undoes: None,
+            undone: false,
+            folders: Vec::new(),
+            created: false,
+        }
+    }
+
+    /// Loads a journal by its run ID.
+    pub fn load(id: &str) -> anyhow::Result<Journal> {
+        let path = journal_dir()?.join(format!("{id}.json"));
+
+        let data =
+            fs::read_to_string(&path).with_context(|| format!("No run found with ID {id}"))?;
+
+        serde_json::from_str(&data).with_context(|| format!("Invalid journal: {}", path.display()))
+    }
+
+    /// Lists all recorded journals, newest first.
+    pub fn list() -> anyhow::Result<Vec<Journal>> {
+        let dir = journal_dir()?;
+
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut journals = Vec::new();
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            match fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_str(&data)?))
+            {
+                Ok(journal) => journals.push(journal),
+                Err(e) => warn!("Skipping invalid journal {}: {}", path.display(), e),
+            }
+        }
+
+        journals.sort_by(|a: &Journal, b| b.id.cmp(&a.id));
+
+        Ok(journals)
+    }
+
+    /// Writes a new journal to the state directory.
+    ///
+    /// If another run already has the same ID (e.g. because it started in the same
+    /// millisecond), a suffix is added to this run's ID so that neither is overwritten.
+    pub fn create(&mut self) -> anyhow::Result<()> {
+        self.create_in(&journal_dir()?)
+    }
+
+    fn create_in(&mut self, dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dir)?;
+
+        let id = self.id.clone();
+        let mut suffix = 1;
+
+        // The ID is claimed by creating its file, so that two runs can't both take it.
+        loop {
+            match File::create_new(dir.join(format!("{}.json", self.id))) {
+                Ok(_) => break,
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    self.id = format!("{id}-{suffix}");
+                    suffix += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        self.created = true;
+
+        self.save_in(dir)
+    }
+
+    /// Returns whether the journal has been written to the state directory.
+    pub fn is_created(&self) -> bool {
+        self.created
+    }
+
+    /// Adds the changes made to a folder, saving the journal straight away so that
+    /// they can still be undone if the run is interrupted.
+    ///
+    /// The journal should be created before the changes are written, so that the
+    /// run's ID is claimed up front.
+    pub fn record(&mut self, changes: FolderChanges) -> anyhow::Result<()> {
+        self.folders.push(changes);
+        self.save()
+    }
+
+    /// Deletes the journal from the state directory, if it was written there.
+    pub fn discard(&self) -> anyhow::Result<()> {
+        if self.created {
+            fs::remove_file(journal_dir()?.join(format!("{}.json", self.id)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the journal to the state directory, replacing the existing version.
+    pub fn save(&self) -> anyhow::Result<()> {
+        self.save_in(&journal_dir()?)
+    }
+
+    fn save_in(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dir)?;
+
+        let path = dir.join(format!("{}.json", self.id));
+        let temp_path = dir.join(format!(".{}.json.tmp", self.id));
+
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &path)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the directory that journals are stored in.
+///
+/// This can be overridden via the `LIVETAGGER_STATE_DIR` environment variable.
+#[cfg(not(test))]
+fn journal_dir() -> anyhow::Result<PathBuf> {
+    let state_dir = match env::var_os("LIVETAGGER_STATE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => dirs::data_local_dir()
+            .context("Could not find a local data directory")?
+            .join("LiveTagger"),
+    };
+
+    Ok(state_dir.join("journal"))
+}
+
+/// Tests that commit changes write their journals to a temporary directory, rather
+/// than into the real run history.
+#[cfg(test)]
+fn journal_dir() -> anyhow::Result<PathBuf> {
+    Ok(env::temp_dir().join(format!("livetagger-journal-{}", std::process::id())))
+}
+
+/// Works out how the keywords and colors of each item in a document have changed.
+pub fn diff_items(before: &[Item], after: &FolderMetadata) -> Vec<ItemChanges> {
+    let mut before_by_file: HashMap<&str, &Item> = HashMap::new();
+
+    for item in before {
+        before_by_file.entry(&item.file_path).or_insert(item);
+    }
+
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+
+    for item in after.items() {
+        if !seen.insert(item.file_path.as_str()) {
+            continue;
+        }
+
+        let old = before_by_file.get(item.file_path.as_str()).copied();
+        let change = diff_item(&item.file_path, old, Some(item));
+
+        if !change.is_empty() {
+            changes.push(change);
+        }
+    }
+
+    // Items which were deleted from the document entirely.
+    for item in before {
+        if seen.insert(item.file_path.as_str()) {
+            let change = diff_item(&item.file_path, Some(item), None);
+
+            if !change.is_empty() {
+                changes.push(change);
+            }
+        }
+    }
+
+    changes
+}
+
+fn diff_item(file: &str, before: Option<&Item>, after: Option<&Item>) -> ItemChanges {
+    let empty = Item::default();
+    let before = before.unwrap_or(&empty);
+    let after = after.unwrap_or(&empty);
+
+    ItemChanges {
+        file: file.to_string(),
+        keywords_added: difference(&after.keywords, &before.keywords),
+        keywords_removed: difference(&before.keywords, &after.keywords),
+        colors_added: difference(&after.colors, &before.colors),
+        colors_removed: difference(&before.colors, &after.colors),
+        ..ItemChanges::default()
+    }
+}
+
+/// Reverses a set of changes to a document.
+///
+/// Only the recorded changes are reversed, so any other edits made to the same
+/// items since will be left intact.
+pub fn revert_changes(doc: &mut FolderMetadata, changes: &[ItemChanges]) -> ChangeSet {
+    let inverted: Vec<ItemChanges> = changes.iter().map(ItemChanges::inverted).collect();
+
+    plan::apply_changes(doc, &inverted)
+}
+
+/// Logs the recorded runs, newest first.
+pub fn log_runs() -> anyhow::Result<()> {
+    for journal in Journal::list()? {
+        let status = match (&journal.undoes, journal.undone) {
+            (_, true) => " (undone)".to_string(),
+            (Some(undoes), false) => format!(" (undoes {undoes})"),
+            (None, false) => String::new(),
+        };
+
+        info!(
+            "{}: livetagger {} ({} folders){}",
+            journal.id,
+            journal.command.join(" "),
+            journal.folders.len(),
+            status
+        );
+    }
+
+    Ok(())
+}
+
+/// Undoes a previous run's changes.
+///
+/// If no ID is provided, the most recent run that has not already been undone (and
+/// which is not itself an undo, or a run that was interrupted before it changed
+/// anything) is chosen.
+pub fn undo(id: Option<&str>, save: &SaveArgs) -> anyhow::Result<()> {
+    let mut target = match id {
+        Some(id) => Journal::load(id)?,
+        None => Journal::list()?
+            .into_iter()
+            .find(|j| !j.undone && j.undoes.is_none() && !j.folders.is_empty())
+            .context("There are no runs to undo")?,
+    };
+
+    if target.undone {
+        bail!("Run {} has already been undone", target.id);
+    }
+
+    info!(
+        "Undoing run {}: livetagger {}",
+        target.id,
+        target.command.join(" ")
+    );
+
+    let folders = target
+        .folders
+        .iter()
+        .map(|f| {
+            let files = f.items.iter().map(|i| i.file.clone()).collect();
+            (f.folder.clone(), files)
+        })
+        .collect();
+
+    let changes: HashMap<&PathBuf, &[ItemChanges]> = target
+        .folders
+        .iter()
+        .map(|f| (&f.folder, f.items.as_slice()))
+        .collect();
+
+    let run_id = process_folders(save, folders, |folder, doc, _| {
+        Ok(match changes.get(&folder.to_path_buf()) {
+            Some(changes) => revert_changes(doc, changes),
+            None => ChangeSet::default(),
+        })
+    })?;
+
+    if save.commit {
+        if let Some(run_id) = run_id {
+            let mut journal = Journal::load(&run_id)?;
+            journal.undoes = Some(target.id.clone());
+            journal.save()?;
+        }
+
+        target.undone = true;
+        target.save()?;
+
+        // Undoing an undo re-applies the original run's changes.
+        if let Some(original) = &target.undoes {
+            match Journal::load(original) {
+                Ok(mut original) => {
+                    original.undone = false;
+                    original.save()?;
+                }
+                Err(e) => warn!("Could not update run {}: {}", original, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_not_overwrite_journals_with_the_same_id() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let mut first = Journal::new();
+        let mut second = Journal::new();
+        second.id = first.id.clone();
+
+        first.create_in(dir.path())?;
+        second.create_in(dir.path())?;
+
+        assert_eq!(second.id, format!("{}-1", first.id));
+        assert!(dir.path().join(format!("{}.json", first.id)).exists());
+        assert!(dir.path().join(format!("{}.json", second.id)).exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_revert_changes() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+        let before: Vec<Item> = meta.items().cloned().collect();
+
+        meta.get_or_insert_item("bd1.wav").add_keyword("CustomTag");
+        meta.get_or_insert_item("bd2.wav")
+            .remove_keyword("Creator|17cupsofcoffee");
+        meta.get_or_insert_item("bd3.wav").add_color(2);
+        meta.delete_item("bd1.wav");
+
+        let changes = diff_items(&before, &meta);
+
+        assert_eq!(
+            changes,
+            [
+                ItemChanges {
+                    file: "bd2.wav".into(),
+                    keywords_removed: vec!["Creator|17cupsofcoffee".into()],
+                    ..ItemChanges::default()
+                },
+                ItemChanges {
+                    file: "bd3.wav".into(),
+                    colors_added: vec![2],
+                    ..ItemChanges::default()
+                },
+                ItemChanges {
+                    file: "bd1.wav".into(),
+                    keywords_removed: vec!["Drums|Kick".into(), "Creator|17cupsofcoffee".into()],
+                    colors_removed: vec![1],
+                    ..ItemChanges::default()
+                },
+            ]
+        );
+
+        // Edits made after the run should survive the undo.
+        meta.get_or_insert_item("bd2.wav")
+            .add_keyword("Drums|Snare");
+
+        revert_changes(&mut meta, &changes);
+
+        let bd1 = meta.find_item("bd1.wav").unwrap();
+        let bd2 = meta.find_item("bd2.wav").unwrap();
+        let bd3 = meta.find_item("bd3.wav").unwrap();
+
+        assert_eq!(bd1.keywords, ["Drums|Kick", "Creator|17cupsofcoffee"]);
+        assert_eq!(bd1.colors, [1]);
+        assert_eq!(bd2.keywords, ["Drums|Snare", "Creator|17cupsofcoffee"]);
+        assert!(bd3.colors.is_empty());
+
+        Ok(())
+    }
+}
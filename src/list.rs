@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use livemeta::FolderMetadata;
+use serde::Serialize;
+
+/// The output formats supported when listing tags.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ListFormat {
+    /// One line per file.
+    #[default]
+    Plain,
+
+    /// Files grouped by folder, with one line per tag.
+    Tree,
+
+    /// A JSON array, with one object per file.
+    Json,
+}
+
+/// The tags and colors currently applied to a single file.
+#[derive(Debug, Serialize)]
+pub struct FileTags {
+    pub folder: PathBuf,
+    pub file: String,
+    pub keywords: Vec<String>,
+    pub colors: Vec<u32>,
+}
+
+/// Reads the tags of each file from its folder's metadata, sorted by folder and then filename.
+///
+/// Files which don't have an entry in their folder's metadata are included, with no tags.
+pub fn collect_tags(folders: HashMap<PathBuf, HashSet<String>>) -> anyhow::Result<Vec<FileTags>> {
+    let mut entries = Vec::new();
+
+    for (folder, files) in folders {
+        let xmp_path = livemeta::get_folder_metadata_path(&folder);
+
+        let xmp = if xmp_path.exists() {
+            Some(FolderMetadata::from_xmp_file(&xmp_path)?)
+        } else {
+            None
+        };
+
+        for file in files {
+            let item = xmp.as_ref().and_then(|xmp| xmp.find_item(&file));
+
+            entries.push(FileTags {
+                folder: folder.clone(),
+                keywords: item.map(|i| i.keywords.clone()).unwrap_or_default(),
+                colors: item.map(|i| i.colors.clone()).unwrap_or_default(),
+                file,
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| (&a.folder, &a.file).cmp(&(&b.folder, &b.file)));
+
+    Ok(entries)
+}
+
+/// Renders a list of files and their tags in the given format.
+pub fn render(entries: &[FileTags], format: ListFormat) -> anyhow::Result<String> {
+    match format {
+        ListFormat::Plain => Ok(render_plain(entries)),
+        ListFormat::Tree => Ok(render_tree(entries)),
+        ListFormat::Json => Ok(serde_json::to_string_pretty(entries)? + "\n"),
+    }
+}
+
+fn render_plain(entries: &[FileTags]) -> String {
+    let mut out = String::new();
+
+    for entry in entries {
+        let _ = write!(out, "{}", entry.folder.join(&entry.file).display());
+
+        if !entry.colors.is_empty() {
+            let _ = write!(out, " {}", format_colors(&entry.colors));
+        }
+
+        let _ = writeln!(out, ": {}", entry.keywords.join(", "));
+    }
+
+    out
+}
+
+fn render_tree(entries: &[FileTags]) -> String {
+    let mut out = String::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let first_in_folder = i == 0 || entries[i - 1].folder != entry.folder;
+        let last_in_folder = entries
+            .get(i + 1)
+            .is_none_or(|next| next.folder != entry.folder);
+
+        if first_in_folder {
+            if i > 0 {
+                out.push('\n');
+            }
+
+            let folder = entry.folder.display().to_string();
+            let _ = writeln!(out, "{}", if folder.is_empty() { "." } else { &folder });
+        }
+
+        let (branch, indent) = if last_in_folder {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let _ = write!(out, "{branch}{}", entry.file);
+
+        if !entry.colors.is_empty() {
+            let _ = write!(out, " {}", format_colors(&entry.colors));
+        }
+
+        out.push('\n');
+
+        for (j, keyword) in entry.keywords.iter().enumerate() {
+            let branch = if j + 1 == entry.keywords.len() {
+                "└── "
+            } else {
+                "├── "
+            };
+
+            let _ = writeln!(out, "{indent}{branch}{keyword}");
+        }
+    }
+
+    out
+}
+
+fn format_colors(colors: &[u32]) -> String {
+    let colors: Vec<String> = colors.iter().map(u32::to_string).collect();
+
+    format!("[colors: {}]", colors.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<FileTags> {
+        vec![
+            FileTags {
+                folder: PathBuf::from("Drums"),
+                file: "bd1.wav".into(),
+                keywords: vec!["Drums|Kick".into(), "Creator|17cupsofcoffee".into()],
+                colors: vec![1],
+            },
+            FileTags {
+                folder: PathBuf::from("Drums"),
+                file: "bd2.wav".into(),
+                keywords: vec!["Creator|17cupsofcoffee".into()],
+                colors: vec![],
+            },
+        ]
+    }
+
+    #[test]
+    fn should_render_plain() -> anyhow::Result<()> {
+        let expected = format!(
+            "{} [colors: 1]: Drums|Kick, Creator|17cupsofcoffee\n{}: Creator|17cupsofcoffee\n",
+            PathBuf::from("Drums").join("bd1.wav").display(),
+            PathBuf::from("Drums").join("bd2.wav").display(),
+        );
+
+        pretty_assertions::assert_eq!(render(&entries(), ListFormat::Plain)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_render_tree() -> anyhow::Result<()> {
+        let expected = "\
+Drums
+├── bd1.wav [colors: 1]
+│   ├── Drums|Kick
+│   └── Creator|17cupsofcoffee
+└── bd2.wav
+    └── Creator|17cupsofcoffee
+";
+
+        pretty_assertions::assert_eq!(render(&entries(), ListFormat::Tree)?, expected);
+
+        Ok(())
+    }
+}
@@ -1,16 +1,48 @@
+mod backup;
+mod changes;
 mod commands;
+mod csv;
+mod diff;
+mod exclude;
+mod export;
+mod filelist;
+mod import;
+mod journal;
+mod list;
+mod mv;
+mod paths;
+mod plan;
+mod rules;
+mod snapshot;
+mod stats;
+mod template;
 
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
 
-use anyhow::Context;
+use anyhow::{Context, bail};
+use changes::{ChangeSet, OutputFormat, Report};
 use clap::{Args, Parser, Subcommand};
+use exclude::Exclusions;
+use export::ExportFormat;
 use glob::glob;
+use import::ImportFormat;
+use jiff::Timestamp;
+use journal::{FolderChanges, Journal};
+use list::ListFormat;
+use paths::PathTags;
+use plan::{Plan, PlannedFolder};
+use regex::Regex;
+use rules::Rules;
+use snapshot::Snapshot;
+use stats::StatsFormat;
+use template::TagTemplates;
 
-use livemeta::{self, FolderMetadata};
+use livemeta::{self, FolderMetadata, Item, Query, Tag};
 use tracing::{info, warn};
 
 #[derive(Parser, Debug)]
@@ -24,29 +56,171 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Command {
     /// Adds tags to a set of files.
-    Add(TagChangeArgs),
+    Add(AddArgs),
 
     /// Removes tags from a set of files.
-    Remove(TagChangeArgs),
+    Remove(RemoveArgs),
 
     /// Removes all tags from a set of files.
-    RemoveAll(FilesystemArgs),
+    RemoveAll(RemoveAllArgs),
+
+    /// Sets or clears the color labels on a set of files.
+    Color(ColorChangeArgs),
+
+    /// Removes metadata entries for files that no longer exist.
+    ///
+    /// Every folder containing a file matched by the include glob will be pruned.
+    Prune(FilesystemArgs),
+
+    /// Moves samples to another folder, carrying their tags and colors with them.
+    Mv(MoveArgs),
+
+    /// Restores folder metadata from a backup.
+    ///
+    /// Every folder containing a file matched by the include glob will be restored.
+    Restore(RestoreArgs),
+
+    /// Reverses the tag and color changes made by a previous committed run.
+    ///
+    /// Only the recorded changes are reversed, so edits made since then are kept.
+    Undo(UndoArgs),
+
+    /// Lists the tags and colors currently applied to a set of files.
+    List(ListArgs),
+
+    /// Lists the files whose tags match a query.
+    ///
+    /// Queries are made up of tags, combined with AND, OR, NOT and parentheses. A tag
+    /// also matches any tags nested within it, unless it is prefixed with '='. Tags can
+    /// contain '*' and '?' wildcards, and can be wrapped in double quotes if they
+    /// contain spaces.
+    Find(FindArgs),
+
+    /// Renames a tag in the metadata of every folder under a root folder.
+    RenameTag(RenameTagArgs),
+
+    /// Replaces several tags with a single tag, in the metadata of every folder under
+    /// a root folder.
+    MergeTags(MergeTagsArgs),
+
+    /// Replaces a tag with several tags, in the metadata of every folder under a root
+    /// folder.
+    SplitTags(SplitTagsArgs),
+
+    /// Reports how often each tag is used, and how many files are untagged, across
+    /// every folder under a root folder.
+    Stats(StatsArgs),
+
+    /// Writes every tag and color in the metadata of every folder under a root folder
+    /// to standard output, with one record per tag or color.
+    Export(ExportArgs),
+
+    /// Applies tags and colors from a file in the format written by `export`.
+    ///
+    /// Only the files listed in the import are changed.
+    Import(ImportArgs),
+
+    /// Tags the samples under a root folder using a file of pattern-based rules.
+    ///
+    /// The rules file is written in TOML, with a `[[rule]]` table for each rule. Each
+    /// rule has either a `glob` or a `regex` pattern, and a list of `tags` to add to
+    /// the samples whose paths (relative to the root) match it.
+    ApplyRules(ApplyRulesArgs),
+
+    /// Tags the samples under a root folder based on the names of the folders they're in.
+    ///
+    /// The mapping file is written in TOML. Its `tags` are added to every sample, and can
+    /// contain `{component0}`, `{component1}`, etc. placeholders for the folders below the
+    /// root. Its `[folders]` table maps folder names to tags for the samples inside them.
+    TagPaths(TagPathsArgs),
+
+    /// Applies the changes from a plan file written with --plan-out.
+    ///
+    /// If the metadata for any of the folders in the plan has changed since it was
+    /// written, nothing will be applied.
+    Apply(ApplyArgs),
 }
 
 /// CLI flags for operating on files.
 #[derive(Args, Debug)]
 struct FilesystemArgs {
-    /// A glob pattern specifying which files should be processed.
+    #[command(flatten)]
+    select: SelectArgs,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for selecting files.
+#[derive(Args, Debug)]
+struct SelectArgs {
+    /// A glob pattern specifying which files should be included. Can be passed multiple times.
     #[arg(short, long, global(true), value_name = "GLOB", default_value = "*")]
-    include: String,
+    include: Vec<String>,
+
+    /// A glob pattern specifying files or folders to skip. Can be passed multiple times.
+    ///
+    /// Paths listed in a .livetaggerignore file in the current folder (using gitignore
+    /// syntax) are also skipped.
+    #[arg(short = 'x', long, global(true), value_name = "GLOB")]
+    exclude: Vec<String>,
 
+    /// Reads the paths of the files to include from a file (or standard input, if "-"),
+    /// rather than using globs. Paths can be separated by newlines or NUL characters.
+    #[arg(long, global(true), value_name = "PATH", conflicts_with("include"))]
+    files_from: Option<String>,
+}
+
+/// CLI flags for saving changes.
+#[derive(Args, Debug)]
+struct SaveArgs {
     /// Saves changes to the filesystem. Run without this first, to make sure you're tagging the correct files!
     #[arg(short, long, global(true))]
     commit: bool,
 
-    /// Creates backups of any changed metadata.
+    /// Creates timestamped backups of any changed metadata.
     #[arg(short, long, global(true))]
     backup: bool,
+
+    /// The number of backups to keep for each folder (at least 1). Older backups are deleted.
+    #[arg(long, global(true), value_name = "COUNT", default_value_t = NonZeroUsize::new(10).unwrap())]
+    keep_backups: NonZeroUsize,
+
+    /// The format to report changes in.
+    #[arg(short, long, global(true), value_enum, default_value_t)]
+    output: OutputFormat,
+
+    /// Prints a unified diff of the changes to each folder's metadata file.
+    #[arg(long, global(true), conflicts_with("output"))]
+    diff: bool,
+
+    /// Writes the changes to a plan file instead of applying them, so that they can be
+    /// reviewed and then applied with `livetagger apply`.
+    #[arg(long, global(true), value_name = "PATH", conflicts_with("commit"))]
+    plan_out: Option<PathBuf>,
+}
+
+#[cfg(test)]
+impl SaveArgs {
+    /// Returns the flags for a committed run with no other options, for use in tests.
+    fn committed() -> SaveArgs {
+        SaveArgs {
+            commit: true,
+            backup: false,
+            keep_backups: NonZeroUsize::new(10).unwrap(),
+            output: OutputFormat::Text,
+            diff: false,
+            plan_out: None,
+        }
+    }
+}
+
+/// CLI flags for filtering files by their existing tags.
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// A query specifying which tags the files must already have, e.g. "Drums|Kick NOT Loop".
+    #[arg(short, long = "where", value_name = "QUERY")]
+    where_: Option<Query>,
 }
 
 /// CLI flags for batch tag operations.
@@ -54,95 +228,847 @@ struct FilesystemArgs {
 struct TagChangeArgs {
     /// The tags to apply to the matched files.
     #[arg(required(true))]
+    tags: Vec<Tag>,
+
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    fs: FilesystemArgs,
+}
+
+/// CLI flags for removing tags.
+#[derive(Args, Debug)]
+struct RemoveArgs {
+    /// The tags to remove from the matched files. These match both the tag as written
+    /// and its tidied-up form, so that malformed tags (e.g. with an empty part) can
+    /// still be removed.
+    #[arg(required(true))]
     tags: Vec<String>,
 
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    fs: FilesystemArgs,
+}
+
+/// CLI flags for adding tags.
+#[derive(Args, Debug)]
+struct AddArgs {
+    /// Only tag files whose names match this regex. Its captures can be used in the
+    /// tags as placeholders, e.g. "BPM|{bpm}" for a capture named "bpm".
+    #[arg(short, long = "match", value_name = "REGEX")]
+    match_: Option<Regex>,
+
+    #[command(flatten)]
+    change: TagChangeArgs,
+}
+
+/// CLI flags for removing all tags.
+#[derive(Args, Debug)]
+struct RemoveAllArgs {
+    #[command(flatten)]
+    filter: FilterArgs,
+
+    #[command(flatten)]
+    fs: FilesystemArgs,
+}
+
+/// CLI flags for batch color operations.
+#[derive(Args, Debug)]
+struct ColorChangeArgs {
+    /// The colors to apply to the matched files, numbered 1 to 7 in the order Live displays them.
+    #[arg(
+        required_unless_present("clear"),
+        value_parser(clap::value_parser!(u32).range(1..=7))
+    )]
+    colors: Vec<u32>,
+
+    /// Removes all colors from the matched files.
+    #[arg(long, conflicts_with("colors"))]
+    clear: bool,
+
+    #[command(flatten)]
+    fs: FilesystemArgs,
+}
+
+/// CLI flags for moving samples.
+#[derive(Args, Debug)]
+struct MoveArgs {
+    /// The samples to move.
+    #[arg(required(true))]
+    sources: Vec<PathBuf>,
+
+    /// The folder to move the samples into. If a single sample is being moved, this
+    /// can also be its new path.
+    dest: PathBuf,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for operating on every folder under a root folder.
+#[derive(Args, Debug)]
+struct LibraryArgs {
+    /// The folder to search for metadata. Nested folders are included.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// A glob pattern specifying folders to skip. Can be passed multiple times.
+    ///
+    /// Paths listed in a .livetaggerignore file in the root folder are also skipped.
+    #[arg(short = 'x', long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for renaming tags.
+#[derive(Args, Debug)]
+struct RenameTagArgs {
+    /// The tag to rename. This matches both the tag as written and its tidied-up
+    /// form, so that malformed tags (e.g. with an empty part) can still be renamed.
+    old: String,
+
+    /// The new name for the tag.
+    new: Tag,
+
+    /// Also renames the tags nested within the old tag, e.g. `Drums|HiHat|Closed`.
+    #[arg(short, long)]
+    descendants: bool,
+
+    #[command(flatten)]
+    library: LibraryArgs,
+}
+
+/// CLI flags for merging tags.
+#[derive(Args, Debug)]
+struct MergeTagsArgs {
+    /// The tags to merge. These match both the tags as written and their tidied-up
+    /// forms, so that malformed tags (e.g. with an empty part) can still be merged.
+    #[arg(required(true))]
+    tags: Vec<String>,
+
+    /// The tag to replace them with.
+    #[arg(long, value_name = "TAG")]
+    into: Tag,
+
+    #[command(flatten)]
+    library: LibraryArgs,
+}
+
+/// CLI flags for splitting tags.
+#[derive(Args, Debug)]
+struct SplitTagsArgs {
+    /// The tag to split. This matches both the tag as written and its tidied-up
+    /// form, so that malformed tags (e.g. with an empty part) can still be split.
+    tag: String,
+
+    /// The tags to replace it with.
+    #[arg(long, value_name = "TAG", required(true), num_args(1..))]
+    into: Vec<Tag>,
+
+    #[command(flatten)]
+    library: LibraryArgs,
+}
+
+/// CLI flags for reporting tag statistics.
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// The folder to search for samples. Nested folders are included.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// The format to print the statistics in.
+    #[arg(short, long, value_enum, default_value_t)]
+    format: StatsFormat,
+}
+
+/// CLI flags for exporting tags.
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// The folder to search for metadata. Nested folders are included, and exported
+    /// paths are relative to this folder.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// The format to write the records in.
+    #[arg(short, long, value_enum, default_value_t)]
+    format: ExportFormat,
+}
+
+/// CLI flags for importing tags.
+#[derive(Args, Debug)]
+struct ImportArgs {
+    /// The file to import.
+    file: PathBuf,
+
+    /// The format of the file. Defaults to JSON if the file has a .json extension,
+    /// and CSV otherwise.
+    #[arg(short, long, value_enum)]
+    format: Option<ImportFormat>,
+
+    /// The folder that paths in the file are relative to.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// Replaces each file's tags and colors with the imported ones, rather than
+    /// adding to them.
+    #[arg(long)]
+    replace: bool,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for applying a rules file.
+#[derive(Args, Debug)]
+struct ApplyRulesArgs {
+    /// The rules file to apply.
+    rules: PathBuf,
+
+    /// The folder to search for samples. Nested folders are included, and paths are
+    /// matched relative to this folder.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// A glob pattern specifying files or folders to skip. Can be passed multiple times.
+    ///
+    /// Paths listed in a .livetaggerignore file in the root folder are also skipped.
+    #[arg(short = 'x', long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for tagging samples by their paths.
+#[derive(Args, Debug)]
+struct TagPathsArgs {
+    /// The mapping file to apply.
+    mapping: PathBuf,
+
+    /// The folder to search for samples. Nested folders are included, and folder
+    /// names are counted from this folder.
+    #[arg(short, long, value_name = "DIR", default_value = ".")]
+    root: PathBuf,
+
+    /// A glob pattern specifying files or folders to skip. Can be passed multiple times.
+    ///
+    /// Paths listed in a .livetaggerignore file in the root folder are also skipped.
+    #[arg(short = 'x', long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for restoring backups.
+#[derive(Args, Debug)]
+struct RestoreArgs {
+    /// Lists the available backups, rather than restoring one.
+    #[arg(short, long)]
+    list: bool,
+
+    /// The ID of the backup to restore, as shown by --list. Defaults to the most recent backup.
+    #[arg(long, value_name = "ID", conflicts_with("list"))]
+    from: Option<String>,
+
     #[command(flatten)]
     fs: FilesystemArgs,
 }
 
+/// CLI flags for undoing runs.
+#[derive(Args, Debug)]
+struct UndoArgs {
+    /// The ID of the run to undo, as shown by --list. Defaults to the most recent run.
+    run_id: Option<String>,
+
+    /// Lists the runs that have been recorded, rather than undoing one.
+    #[arg(short, long, conflicts_with("run_id"))]
+    list: bool,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for applying plans.
+#[derive(Args, Debug)]
+struct ApplyArgs {
+    /// The plan file to apply.
+    plan: PathBuf,
+
+    #[command(flatten)]
+    save: SaveArgs,
+}
+
+/// CLI flags for listing tags.
+#[derive(Args, Debug)]
+struct ListArgs {
+    #[command(flatten)]
+    select: SelectArgs,
+
+    /// The format to print the tags in.
+    #[arg(short, long, value_enum, default_value_t)]
+    format: ListFormat,
+}
+
+/// CLI flags for finding files by their tags.
+#[derive(Args, Debug)]
+struct FindArgs {
+    /// The query to match files against, e.g. "Drums|Kick NOT Loop".
+    query: Query,
+
+    #[command(flatten)]
+    list: ListArgs,
+}
+
+impl Command {
+    /// Returns the flags for saving changes, if the command makes any.
+    fn save_args(&self) -> Option<&SaveArgs> {
+        match self {
+            Command::Add(args) => Some(&args.change.fs.save),
+            Command::Remove(args) => Some(&args.fs.save),
+            Command::RemoveAll(args) => Some(&args.fs.save),
+            Command::Color(args) => Some(&args.fs.save),
+            Command::Prune(args) => Some(&args.save),
+            Command::Mv(args) => Some(&args.save),
+            Command::Restore(args) => Some(&args.fs.save),
+            Command::Undo(args) => Some(&args.save),
+            Command::RenameTag(args) => Some(&args.library.save),
+            Command::MergeTags(args) => Some(&args.library.save),
+            Command::SplitTags(args) => Some(&args.library.save),
+            Command::Import(args) => Some(&args.save),
+            Command::ApplyRules(args) => Some(&args.save),
+            Command::TagPaths(args) => Some(&args.save),
+            Command::Apply(args) => Some(&args.save),
+
+            Command::List(_) | Command::Find(_) | Command::Stats(_) | Command::Export(_) => None,
+        }
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
-    tracing_subscriber::fmt().with_target(false).init();
+    // Standard output is reserved for the results of read-only commands, and for the
+    // report or diffs when they've been requested, so that they can be piped into
+    // other tools.
+    let log_to_stderr = match cli.command.save_args() {
+        Some(save) => save.output == OutputFormat::Json || save.diff,
+        None => true,
+    };
+
+    if log_to_stderr {
+        tracing_subscriber::fmt()
+            .with_target(false)
+            .with_writer(io::stderr)
+            .init();
+    } else {
+        tracing_subscriber::fmt().with_target(false).init();
+    }
 
     match cli.command {
-        Command::Add(args) => process_xmp(&args.fs, |doc, files| {
-            commands::add_tags(doc, files, &args.tags)
-        })?,
+        Command::Add(args) => {
+            let templates = match args.match_ {
+                Some(regex) => Some(TagTemplates::new(regex, args.change.tags.clone())?),
+                None => None,
+            };
+
+            process_xmp(&args.change.fs, |doc, files| {
+                let files = commands::filter_files(doc, files, args.change.filter.where_.as_ref());
+
+                commands::add_tags(doc, files, |file| match &templates {
+                    Some(templates) => templates.tags_for(file),
+                    None => args.change.tags.clone(),
+                })
+            })?
+        }
 
         Command::Remove(args) => process_xmp(&args.fs, |doc, files| {
+            let files = commands::filter_files(doc, files, args.filter.where_.as_ref());
             commands::remove_tags(doc, files, &args.tags)
         })?,
 
-        Command::RemoveAll(args) => process_xmp(&args, commands::remove_all_tags)?,
+        Command::RemoveAll(args) => process_xmp(&args.fs, |doc, files| {
+            let files = commands::filter_files(doc, files, args.filter.where_.as_ref());
+            commands::remove_all_tags(doc, files)
+        })?,
+
+        Command::Color(args) => process_xmp(&args.fs, |doc, files| {
+            commands::set_colors(doc, files, &args.colors)
+        })?,
+
+        Command::Prune(args) => {
+            let folders = search_for_parent_folders(&args.select, |folder| {
+                livemeta::get_folder_metadata_path(folder).exists()
+            })?
+            .into_iter()
+            .map(|folder| (folder, HashSet::new()))
+            .collect();
+
+            process_folders(&args.save, folders, |folder, doc, _| {
+                commands::prune_items(doc, |file| folder.join(file).exists())
+            })?;
+        }
+
+        Command::Mv(args) => mv::move_samples(&args.sources, &args.dest, &args.save)?,
+
+        Command::Restore(args) => {
+            let folders = search_for_parent_folders(&args.fs.select, |folder| {
+                !backup::list_backups(&livemeta::get_folder_metadata_path(folder))
+                    .unwrap_or_default()
+                    .is_empty()
+            })?;
+
+            if args.list {
+                backup::log_backups(&folders)?;
+            } else {
+                backup::restore_backups(&folders, args.from.as_deref(), &args.fs.save)?;
+            }
+        }
+
+        Command::List(args) => {
+            let folders = search_for_sample_folders(&args.select)?;
+            let entries = list::collect_tags(folders)?;
+
+            print!("{}", list::render(&entries, args.format)?);
+        }
+
+        Command::Find(args) => {
+            let folders = search_for_sample_folders(&args.list.select)?;
+
+            let mut entries = list::collect_tags(folders)?;
+            entries.retain(|entry| args.query.matches(&entry.keywords));
+
+            print!("{}", list::render(&entries, args.list.format)?);
+        }
+
+        Command::RenameTag(args) => process_library(&args.library, |doc| {
+            commands::rename_tag(doc, &args.old, &args.new, args.descendants)
+        })?,
+
+        Command::MergeTags(args) => process_library(&args.library, |doc| {
+            commands::merge_tags(doc, &args.tags, &args.into)
+        })?,
+
+        Command::SplitTags(args) => process_library(&args.library, |doc| {
+            commands::split_tag(doc, &args.tag, &args.into)
+        })?,
+
+        Command::Stats(args) => {
+            let folders = search_for_folders(&args.root, &[], |_| true)?;
+            let stats = stats::collect_stats(&folders)?;
+
+            print!("{}", stats::render(&stats, args.format)?);
+        }
+
+        Command::Export(args) => {
+            let folders = search_for_metadata_folders(&args.root, &[])?;
+            let records = export::collect_all_records(&args.root, &folders)?;
+
+            print!("{}", export::render(&records, args.format)?);
+        }
+
+        Command::Import(args) => {
+            let data = fs::read_to_string(&args.file)
+                .with_context(|| format!("Could not read {}", args.file.display()))?;
+
+            let format = args
+                .format
+                .unwrap_or_else(|| ImportFormat::from_path(&args.file));
+
+            let records = import::parse_records(&data, format)
+                .with_context(|| format!("Invalid import file: {}", args.file.display()))?;
+
+            let mut imported = import::group_records(&args.root, records)?;
+            import::skip_missing_files(&mut imported);
+
+            let folders = imported
+                .iter()
+                .map(|(folder, files)| (folder.clone(), files.keys().cloned().collect()))
+                .collect();
+
+            process_folders(&args.save, folders, |folder, doc, _| {
+                import::apply_tags(doc, &imported[folder], args.replace)
+            })?;
+        }
+
+        Command::ApplyRules(args) => {
+            let data = fs::read_to_string(&args.rules)
+                .with_context(|| format!("Could not read {}", args.rules.display()))?;
+
+            let rules = Rules::parse(&data)
+                .with_context(|| format!("Invalid rules file: {}", args.rules.display()))?;
+
+            let matched = rules.match_samples(&args.root, &args.exclude)?;
+
+            process_matched_tags(&args.save, &matched)?;
+        }
+
+        Command::TagPaths(args) => {
+            let data = fs::read_to_string(&args.mapping)
+                .with_context(|| format!("Could not read {}", args.mapping.display()))?;
+
+            let mapping = PathTags::parse(&data)
+                .with_context(|| format!("Invalid mapping file: {}", args.mapping.display()))?;
+
+            let matched = mapping.match_samples(&args.root, &args.exclude)?;
+
+            if !args.save.commit {
+                paths::log_derived_tags(&matched);
+            }
+
+            process_matched_tags(&args.save, &matched)?;
+        }
+
+        Command::Undo(args) => {
+            if args.list {
+                journal::log_runs()?;
+            } else {
+                journal::undo(args.run_id.as_deref(), &args.save)?;
+            }
+        }
+
+        Command::Apply(args) => plan::apply_plan(&args.plan, &args.save)?,
     }
 
     Ok(())
 }
 
-/// Finds all files matching the provided parameters, applies some logic to each folder's
-/// metadata document (creating one from scratch if needed), then saves to disk if
-/// changes have been made.
+/// Finds all files matching the provided parameters, then processes the metadata of
+/// their parent folders via [`process_folders`].
 fn process_xmp<F>(args: &FilesystemArgs, mut action: F) -> anyhow::Result<()>
 where
-    F: FnMut(&mut FolderMetadata, HashSet<String>) -> anyhow::Result<()>,
+    F: FnMut(&mut FolderMetadata, HashSet<String>) -> anyhow::Result<ChangeSet>,
 {
-    let folders = search_for_sample_folders(&args.include)?;
+    let folders = search_for_sample_folders(&args.select)?;
 
-    for (folder, files) in folders {
-        info!("Processing {}", folder.display());
+    process_folders(&args.save, folders, |_, doc, files| action(doc, files))?;
 
-        let xmp_path = livemeta::get_folder_metadata_path(&folder);
+    Ok(())
+}
 
-        let (mut xmp, new_file) = if xmp_path.exists() {
-            (FolderMetadata::from_xmp_file(&xmp_path)?, false)
-        } else {
-            (FolderMetadata::new()?, true)
-        };
+/// Finds every folder with metadata under a root folder, then processes them via
+/// [`process_folders`].
+///
+/// The number of files the action changed is reported for each folder.
+fn process_library<F>(args: &LibraryArgs, mut action: F) -> anyhow::Result<()>
+where
+    F: FnMut(&mut FolderMetadata) -> anyhow::Result<ChangeSet>,
+{
+    let folders = search_for_metadata_folders(&args.root, &args.exclude)?
+        .into_iter()
+        .map(|folder| (folder, HashSet::new()))
+        .collect();
 
-        action(&mut xmp, files)?;
+    process_folders(&args.save, folders, |folder, doc, _| {
+        let changes = action(doc)?;
 
-        if xmp.is_dirty() {
-            xmp.set_creator_tool("Updated by LiveTagger")?;
+        if !changes.is_empty() {
+            info!(
+                "Changed tags on {} files in {}",
+                changes.len(),
+                folder.display()
+            );
+        }
 
-            if new_file {
-                xmp.update_create_date()?;
+        Ok(changes)
+    })?;
+
+    Ok(())
+}
+
+/// Adds tags to samples, given the tags for each sample grouped by folder.
+fn process_matched_tags(
+    args: &SaveArgs,
+    matched: &HashMap<PathBuf, HashMap<String, Vec<Tag>>>,
+) -> anyhow::Result<()> {
+    let folders = matched
+        .iter()
+        .map(|(folder, files)| (folder.clone(), files.keys().cloned().collect()))
+        .collect();
+
+    process_folders(args, folders, |folder, doc, files| {
+        let tags = &matched[folder];
+
+        commands::add_tags(doc, files, |file| tags[file].clone())
+    })?;
+
+    Ok(())
+}
+
+/// Applies some logic to the metadata document of each of the provided folders
+/// (creating one from scratch if needed), then saves to disk if changes have been made.
+///
+/// When committing, the changes are recorded in a journal so that they can be undone
+/// later. Returns the ID of the journal, if one was written.
+///
+/// The action returns the changes it made, which are reported at the end of the run
+/// if JSON output was requested.
+fn process_folders<F>(
+    args: &SaveArgs,
+    folders: HashMap<PathBuf, HashSet<String>>,
+    action: F,
+) -> anyhow::Result<Option<String>>
+where
+    F: FnMut(&Path, &mut FolderMetadata, HashSet<String>) -> anyhow::Result<ChangeSet>,
+{
+    process_folders_with_hashes(args, folders, &HashMap::new(), action)
+}
+
+/// Like [`process_folders`], but refuses to change any folder whose metadata doesn't
+/// match the expected hash (or `None`, if it shouldn't exist yet) when it is read.
+fn process_folders_with_hashes<F>(
+    args: &SaveArgs,
+    folders: HashMap<PathBuf, HashSet<String>>,
+    expected_hashes: &HashMap<PathBuf, Option<String>>,
+    mut action: F,
+) -> anyhow::Result<Option<String>>
+where
+    F: FnMut(&Path, &mut FolderMetadata, HashSet<String>) -> anyhow::Result<ChangeSet>,
+{
+    let mut records = RunRecords {
+        report: Report::default(),
+        plan: Plan::new(),
+        journal: Journal::new(),
+    };
+
+    for (folder, files) in folders {
+        let result = process_folder(
+            args,
+            &folder,
+            files,
+            expected_hashes.get(&folder),
+            &mut action,
+            &mut records,
+        );
+
+        if let Err(e) = result {
+            // Whatever was already written is still in the journal, so it can be undone.
+            if records.journal.folders.is_empty() {
+                records.journal.discard()?;
             } else {
-                xmp.update_metadata_date()?;
+                info!("Changes so far recorded as run {}", records.journal.id);
             }
 
-            if args.commit {
-                if args.backup {
-                    let backup_path = xmp_path.with_extension("xmp.bak");
+            return Err(e);
+        }
+    }
 
-                    fs::rename(&xmp_path, &backup_path)?;
-                    info!("Backup written to {}", backup_path.display())
-                }
+    if let Some(plan_path) = &args.plan_out {
+        records.plan.save(plan_path)?;
+        info!(
+            "Plan written to {}. Once it has been reviewed, run `livetagger apply {} --commit` to apply it.",
+            plan_path.display(),
+            plan_path.display()
+        );
+    } else if !args.commit {
+        warn!("Run again with --commit to apply the above changes!");
+    }
 
-                fs::write(&xmp_path, &xmp.to_xml()?)?;
+    if records.journal.folders.is_empty() {
+        records.journal.discard()?;
+        records.report.output(args, None)?;
+        return Ok(None);
+    }
 
-                info!("Metadata updated for {}", folder.display())
-            }
-        } else {
+    info!("Changes recorded as run {}", records.journal.id);
+
+    records
+        .report
+        .output(args, Some(records.journal.id.clone()))?;
+
+    Ok(Some(records.journal.id))
+}
+
+/// The records that a run keeps of the changes it makes to each folder.
+struct RunRecords {
+    report: Report,
+    plan: Plan,
+    journal: Journal,
+}
+
+/// How many times a folder is processed before giving up, if its metadata keeps
+/// being changed by something else while we're working on it.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Applies some logic to a single folder's metadata document.
+///
+/// The changes made by the action are added to the report, and to the plan if one
+/// is being written. Changes that are saved to disk are also recorded in the journal.
+///
+/// If the metadata file is modified by another program (e.g. Live re-indexing the
+/// folder) between being read and being written, the file is read again and the
+/// action is re-applied, rather than overwriting the other program's changes. If an
+/// expected hash was provided, the folder is never re-processed - the changes were
+/// only reviewed against that version of the file, so an error is returned instead.
+fn process_folder<F>(
+    args: &SaveArgs,
+    folder: &Path,
+    files: HashSet<String>,
+    expected_hash: Option<&Option<String>>,
+    action: &mut F,
+    records: &mut RunRecords,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&Path, &mut FolderMetadata, HashSet<String>) -> anyhow::Result<ChangeSet>,
+{
+    info!("Processing {}", folder.display());
+
+    let xmp_path = livemeta::get_folder_metadata_path(folder);
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let (original, snapshot) = Snapshot::read(&xmp_path)?;
+
+        if let Some(expected) = expected_hash
+            && snapshot.hash() != expected.as_deref()
+        {
+            bail!(
+                "The metadata for {} has changed since the plan was made, so it can't be applied safely",
+                folder.display()
+            );
+        }
+
+        let mut xmp = match &original {
+            Some(original) => FolderMetadata::from_xmp_str(original)?,
+            None => FolderMetadata::new()?,
+        };
+
+        let before: Vec<Item> = xmp.items().cloned().collect();
+
+        let changes = action(folder, &mut xmp, files.clone())?;
+
+        if !xmp.is_dirty() {
             info!("No changes required for {}", folder.display());
+            records.report.add_folder(folder, changes, false);
+            return Ok(());
+        }
+
+        stamp_metadata(&mut xmp, original.is_none())?;
+
+        if !args.commit {
+            diff::print_diff(args, &xmp_path, original.as_deref(), &xmp)?;
+
+            if args.plan_out.is_some() {
+                records.plan.add_folder(PlannedFolder {
+                    folder: canonicalize_folder(folder)?,
+                    hash: original.as_deref().map(plan::hash_contents),
+                    items: changes.items.clone(),
+                })?;
+            }
+
+            records.report.add_folder(folder, changes, false);
+            return Ok(());
         }
+
+        // Journals refer to folders by absolute path, so that runs can be undone from
+        // anywhere.
+        let canonical_folder = canonicalize_folder(folder)?;
+
+        if snapshot.has_changed(&xmp_path)? {
+            warn!(
+                "{} was modified by another program while it was being updated (attempt {attempt} of {MAX_ATTEMPTS})",
+                xmp_path.display()
+            );
+
+            continue;
+        }
+
+        diff::print_diff(args, &xmp_path, original.as_deref(), &xmp)?;
+
+        // The journal is created before anything is written, and saved after every
+        // write, so that an interrupted run can still be undone.
+        if !records.journal.is_created() {
+            records.journal.create()?;
+        }
+
+        write_metadata(&xmp_path, &xmp, args)?;
+
+        info!("Metadata updated for {}", folder.display());
+
+        records.report.add_folder(folder, changes, true);
+
+        let items = journal::diff_items(&before, &xmp);
+
+        if !items.is_empty() {
+            records.journal.record(FolderChanges {
+                folder: canonical_folder,
+                items,
+            })?;
+        }
+
+        return Ok(());
     }
 
-    if !args.commit {
-        warn!("Run again with --commit to apply the above changes!");
+    bail!(
+        "Gave up on updating {}, as it kept being modified by another program. Close Live (or wait for it to finish indexing) and try again.",
+        xmp_path.display()
+    )
+}
+
+/// Returns the canonical path of a folder, treating an empty path as the current directory.
+fn canonicalize_folder(folder: &Path) -> io::Result<PathBuf> {
+    if folder.as_os_str().is_empty() {
+        fs::canonicalize(".")
+    } else {
+        fs::canonicalize(folder)
+    }
+}
+
+/// Returns a new ID based on the current time. These sort chronologically when
+/// compared as strings.
+fn new_timestamp_id() -> String {
+    Timestamp::now().strftime("%Y%m%dT%H%M%S%.3fZ").to_string()
+}
+
+/// Marks a changed metadata document as having been updated by LiveTagger.
+fn stamp_metadata(xmp: &mut FolderMetadata, new_file: bool) -> anyhow::Result<()> {
+    xmp.set_creator_tool("Updated by LiveTagger")?;
+
+    if new_file {
+        xmp.update_create_date()?;
+    } else {
+        xmp.update_metadata_date()?;
+    }
+
+    Ok(())
+}
+
+/// Writes a metadata document to disk, optionally backing up the existing file first.
+fn write_metadata(xmp_path: &Path, xmp: &FolderMetadata, save: &SaveArgs) -> anyhow::Result<()> {
+    if save.backup && xmp_path.exists() {
+        let backup_path = backup::create_backup(xmp_path, save.keep_backups)?;
+        info!("Backup written to {}", backup_path.display())
     }
 
+    xmp.save(xmp_path)?;
+
     Ok(())
 }
 
-/// Finds all sample files matching a given glob, as well as their corresponding parent folders.
-fn search_for_sample_folders(include: &str) -> anyhow::Result<HashMap<PathBuf, HashSet<String>>> {
+/// Finds all sample files matching the given globs (or listed in the given file), as
+/// well as their corresponding parent folders.
+fn search_for_sample_folders(
+    args: &SelectArgs,
+) -> anyhow::Result<HashMap<PathBuf, HashSet<String>>> {
+    let exclusions = Exclusions::load(Path::new("."), &args.exclude)?;
     let mut folders: HashMap<PathBuf, HashSet<String>> = HashMap::new();
 
-    for entry in glob(include).context("Invalid include glob")? {
-        let path = entry.context("Invalid path")?;
+    for path in search_paths(args)? {
+        // Globs will match all sorts of files that can be quietly skipped, but if
+        // a path was listed explicitly, it's probably a mistake.
+        if args.files_from.is_some() && !check_listed_path(&path, &exclusions) {
+            continue;
+        }
 
-        if livemeta::is_metadata(&path) || path.is_dir() {
+        if livemeta::is_metadata(&path)
+            || path.is_dir()
+            || path.file_name() == Some(OsStr::new(exclude::IGNORE_FILE))
+            || exclusions.is_excluded(&path, false)
+        {
             continue;
         }
 
@@ -155,25 +1081,316 @@ fn search_for_sample_folders(include: &str) -> anyhow::Result<HashMap<PathBuf, H
             continue;
         }
 
-        let (Some(parent), Some(filename)) =
-            (path.parent(), path.file_name().and_then(OsStr::to_str))
-        else {
+        let Some(filename) = path.file_name().and_then(OsStr::to_str) else {
             continue;
         };
 
-        match folders.get_mut(parent) {
-            Some(folder) => {
-                folder.insert(filename.to_string());
+        let parent = if args.files_from.is_some() {
+            filelist::listed_folder(&path)?
+        } else {
+            match parent_folder(&path) {
+                Some(parent) => parent,
+                None => continue,
             }
+        };
+
+        folders
+            .entry(parent)
+            .or_default()
+            .insert(filename.to_string());
+    }
+
+    Ok(folders)
+}
 
-            None => {
-                let mut set = HashSet::new();
-                set.insert(filename.to_string());
+/// Finds all paths matching any of the given globs, or listed in the given file.
+fn search_paths(args: &SelectArgs) -> anyhow::Result<Vec<PathBuf>> {
+    if let Some(source) = &args.files_from {
+        return filelist::read(source);
+    }
+
+    let mut paths = Vec::new();
+
+    for pattern in &args.include {
+        for entry in glob(pattern).with_context(|| format!("Invalid include glob: {pattern}"))? {
+            paths.push(entry.context("Invalid path")?);
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Checks whether a path from a file list can be processed, logging why not if it can't.
+fn check_listed_path(path: &Path, exclusions: &Exclusions) -> bool {
+    let problem = if !path.exists() {
+        "it doesn't exist"
+    } else if path.is_dir() {
+        "it is a folder"
+    } else if livemeta::is_metadata(path) {
+        "it is Ableton metadata"
+    } else if exclusions.is_excluded(path, false) {
+        "it is excluded"
+    } else {
+        return true;
+    };
+
+    warn!("Skipping {} as {}", path.display(), problem);
+
+    false
+}
+
+/// Finds every folder under a root folder (including the root itself) that has
+/// metadata, sorted by path.
+fn search_for_metadata_folders(root: &Path, excludes: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    search_for_folders(root, excludes, |folder| {
+        livemeta::get_folder_metadata_path(folder).exists()
+    })
+}
 
-                folders.insert(parent.to_path_buf(), set);
+/// Finds every folder under a root folder (including the root itself) which passes
+/// the provided filter, sorted by path.
+///
+/// Folders matching the exclude globs or the root's ignore file are skipped, along
+/// with everything inside them.
+fn search_for_folders(
+    root: &Path,
+    excludes: &[String],
+    mut filter: impl FnMut(&Path) -> bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let exclusions = Exclusions::load(root, excludes)?;
+    let mut folders = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(folder) = pending.pop() {
+        if filter(&folder) {
+            folders.push(folder.clone());
+        }
+
+        let entries = fs::read_dir(&folder)
+            .with_context(|| format!("Could not read {}", folder.display()))?;
+
+        for entry in entries {
+            let entry = entry?;
+
+            let path = entry.path();
+
+            if entry.file_type()?.is_dir()
+                && !livemeta::is_folder_metadata(&path)
+                && !exclusions.is_excluded(&path, true)
+            {
+                pending.push(path);
             }
         }
     }
 
+    folders.sort();
+
     Ok(folders)
 }
+
+/// Finds the sample files in every folder under a root folder, grouped by folder and
+/// sorted by path.
+///
+/// Files and folders matching the exclude globs or the root's ignore file are skipped.
+fn search_for_library_samples(
+    root: &Path,
+    excludes: &[String],
+) -> anyhow::Result<Vec<(PathBuf, Vec<String>)>> {
+    let exclusions = Exclusions::load(root, excludes)?;
+    let mut samples = Vec::new();
+
+    for folder in search_for_folders(root, excludes, |_| true)? {
+        let mut files = list_sample_files(&folder)?;
+        files.retain(|file| !exclusions.is_excluded(&folder.join(file), false));
+
+        if !files.is_empty() {
+            samples.push((folder, files));
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Lists the sample files directly within a folder, sorted by filename.
+fn list_sample_files(folder: &Path) -> anyhow::Result<Vec<String>> {
+    let mut files = Vec::new();
+
+    for entry in fs::read_dir(folder)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if !entry.file_type()?.is_file() || !livemeta::is_supported_sample_format(&path) {
+            continue;
+        }
+
+        if let Some(filename) = path.file_name().and_then(OsStr::to_str) {
+            files.push(filename.to_string());
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Finds the parent folders of all paths matching the given globs, keeping those which
+/// pass the provided filter.
+fn search_for_parent_folders(
+    args: &SelectArgs,
+    mut filter: impl FnMut(&Path) -> bool,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let exclusions = Exclusions::load(Path::new("."), &args.exclude)?;
+    let mut seen = HashSet::new();
+    let mut folders = Vec::new();
+
+    for path in search_paths(args)? {
+        let Some(parent) = parent_folder(&path) else {
+            continue;
+        };
+
+        if exclusions.is_excluded(&path, path.is_dir()) {
+            continue;
+        }
+
+        if livemeta::is_folder_metadata(&parent) || !seen.insert(parent.clone()) {
+            continue;
+        }
+
+        if filter(&parent) {
+            folders.push(parent);
+        }
+    }
+
+    Ok(folders)
+}
+
+/// Returns the folder containing a path.
+///
+/// Any `.` components are removed (and the current folder is always returned as `.`),
+/// so that `Kick.wav` and `./Kick.wav` are grouped into the same folder.
+fn parent_folder(path: &Path) -> Option<PathBuf> {
+    let parent: PathBuf = path
+        .parent()?
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+
+    if parent.as_os_str().is_empty() {
+        Some(PathBuf::from("."))
+    } else {
+        Some(parent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the initial test metadata to a new folder, returning the folder and the
+    /// path of its metadata file.
+    fn metadata_folder(dir: &tempfile::TempDir) -> anyhow::Result<(PathBuf, PathBuf)> {
+        let folder = fs::canonicalize(dir.path())?;
+        let xmp_path = livemeta::get_folder_metadata_path(&folder);
+
+        FolderMetadata::from_xmp_str(include_str!("test_data/initial.xml"))?.save(&xmp_path)?;
+
+        Ok((folder, xmp_path))
+    }
+
+    /// Adds a keyword to an item in a metadata file, as another program might.
+    fn edit_externally(xmp_path: &Path, file: &str, keyword: &str) -> anyhow::Result<()> {
+        let mut xmp = FolderMetadata::from_xmp_file(xmp_path)?;
+        xmp.get_or_insert_item(file).add_keyword(keyword);
+        xmp.save(xmp_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_retry_when_metadata_changes() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (folder, xmp_path) = metadata_folder(&dir)?;
+
+        let mut attempts = 0;
+
+        process_folders(
+            &SaveArgs::committed(),
+            HashMap::from([(folder, HashSet::new())]),
+            |_, doc, _| {
+                attempts += 1;
+
+                if attempts == 1 {
+                    edit_externally(&xmp_path, "bd2.wav", "Live")?;
+                }
+
+                doc.get_or_insert_item("bd1.wav").add_keyword("Loop");
+
+                Ok(ChangeSet::default())
+            },
+        )?;
+
+        let saved = FolderMetadata::from_xmp_file(&xmp_path)?;
+
+        assert_eq!(attempts, 2);
+        assert_eq!(
+            saved.find_item("bd1.wav").unwrap().keywords,
+            ["Drums|Kick", "Creator|17cupsofcoffee", "Loop"]
+        );
+        assert!(
+            saved
+                .find_item("bd2.wav")
+                .unwrap()
+                .keywords
+                .contains(&"Live".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_give_up_when_metadata_keeps_changing() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (folder, xmp_path) = metadata_folder(&dir)?;
+
+        let mut attempts = 0;
+
+        let result = process_folders(
+            &SaveArgs::committed(),
+            HashMap::from([(folder, HashSet::new())]),
+            |_, doc, _| {
+                attempts += 1;
+
+                edit_externally(&xmp_path, "bd2.wav", &format!("Live {attempts}"))?;
+
+                doc.get_or_insert_item("bd1.wav").add_keyword("Loop");
+
+                Ok(ChangeSet::default())
+            },
+        );
+
+        let error = result.unwrap_err();
+
+        assert_eq!(attempts, MAX_ATTEMPTS);
+        assert!(error.to_string().contains("Gave up on updating"));
+
+        // Only the other program's changes made it to disk.
+        let saved = FolderMetadata::from_xmp_file(&xmp_path)?;
+
+        assert!(
+            !saved
+                .find_item("bd1.wav")
+                .unwrap()
+                .keywords
+                .contains(&"Loop".to_string())
+        );
+        assert!(
+            saved
+                .find_item("bd2.wav")
+                .unwrap()
+                .keywords
+                .contains(&format!("Live {MAX_ATTEMPTS}"))
+        );
+
+        Ok(())
+    }
+}
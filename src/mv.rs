@@ -0,0 +1,573 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow, bail};
+use livemeta::{FolderMetadata, Item};
+use tracing::{error, info, warn};
+
+use crate::changes::{ChangeSet, ItemChanges, Report};
+use crate::diff;
+use crate::journal::{self, FolderChanges, Journal};
+use crate::snapshot::Snapshot;
+use crate::{SaveArgs, canonicalize_folder, stamp_metadata, write_metadata};
+
+/// A single sample to be moved.
+struct Move {
+    from_folder: PathBuf,
+    from_name: String,
+    to_folder: PathBuf,
+    to_name: String,
+}
+
+impl Move {
+    fn source(&self) -> PathBuf {
+        self.from_folder.join(&self.from_name)
+    }
+
+    fn target(&self) -> PathBuf {
+        self.to_folder.join(&self.to_name)
+    }
+}
+
+/// A folder's metadata document, along with what was on disk before it was changed.
+struct FolderDoc {
+    xmp_path: PathBuf,
+    xmp: FolderMetadata,
+    original: Option<String>,
+    snapshot: Snapshot,
+    before: Vec<Item>,
+}
+
+/// Moves samples (and their analysis files) to a new location, transferring their
+/// entries from the source folders' metadata to the destination's.
+///
+/// If any step of the move fails, the files and metadata that were already changed
+/// are put back the way they were.
+pub fn move_samples(sources: &[PathBuf], dest: &Path, args: &SaveArgs) -> anyhow::Result<()> {
+    if args.plan_out.is_some() {
+        bail!("Moves can't be written to a plan file");
+    }
+
+    let moves = plan_moves(sources, dest)?;
+
+    let mut docs: HashMap<PathBuf, FolderDoc> = HashMap::new();
+
+    for folder in moves.iter().flat_map(|m| [&m.from_folder, &m.to_folder]) {
+        if !docs.contains_key(folder) {
+            docs.insert(folder.clone(), load_doc(folder)?);
+        }
+    }
+
+    let mut changes: HashMap<PathBuf, ChangeSet> = HashMap::new();
+
+    for m in &moves {
+        info!(
+            "Moving {} to {}",
+            m.source().display(),
+            m.target().display()
+        );
+
+        let Some(mut item) = docs
+            .get_mut(&m.from_folder)
+            .and_then(|doc| doc.xmp.delete_item(&m.from_name))
+        else {
+            continue;
+        };
+
+        if !item.keywords.is_empty() {
+            info!("Carrying tags: {}", item.keywords.join(", "));
+        }
+
+        changes
+            .entry(m.from_folder.clone())
+            .or_default()
+            .push(ItemChanges {
+                file: m.from_name.clone(),
+                keywords_removed: item.keywords.clone(),
+                colors_removed: item.colors.clone(),
+                entry_removed: true,
+                ..ItemChanges::default()
+            });
+
+        changes
+            .entry(m.to_folder.clone())
+            .or_default()
+            .push(ItemChanges {
+                file: m.to_name.clone(),
+                keywords_added: item.keywords.clone(),
+                colors_added: item.colors.clone(),
+                ..ItemChanges::default()
+            });
+
+        item.file_path = m.to_name.clone();
+
+        let to_doc = &mut docs
+            .get_mut(&m.to_folder)
+            .expect("folder should be loaded")
+            .xmp;
+
+        // Any existing entry must be left over from a file that no longer exists,
+        // as we refuse to overwrite files.
+        to_doc.delete_item(&m.to_name);
+        to_doc.push_item(item);
+    }
+
+    // Stamping happens up front, so that any diffs include it.
+    for doc in docs.values_mut() {
+        if doc.xmp.is_dirty() {
+            stamp_metadata(&mut doc.xmp, doc.original.is_none())?;
+            diff::print_diff(args, &doc.xmp_path, doc.original.as_deref(), &doc.xmp)?;
+        }
+    }
+
+    let mut report = Report::default();
+
+    if !args.commit {
+        for (folder, changes) in changes {
+            report.add_folder(&folder, changes, false);
+        }
+
+        warn!("Run again with --commit to apply the above changes!");
+        return report.output(args, None);
+    }
+
+    // Moves can't simply be re-applied if Live has changed the metadata in the
+    // meantime, so bail out before anything is touched.
+    for doc in docs.values() {
+        if doc.xmp.is_dirty() && doc.snapshot.has_changed(&doc.xmp_path)? {
+            bail!(
+                "{} was modified by another program while the move was being prepared, so nothing was moved. Close Live (or wait for it to finish indexing) and try again.",
+                doc.xmp_path.display()
+            );
+        }
+    }
+
+    let mut moved = Vec::new();
+
+    for m in &moves {
+        if let Err(e) = move_sample(&m.source(), &m.target(), &mut moved) {
+            roll_back_files(&moved);
+            return Err(e);
+        }
+    }
+
+    // Like any other run, the metadata changes are journaled as they're written, so
+    // that they can be undone. Undoing a move doesn't move the files back, though.
+    let mut journal = Journal::new();
+
+    if let Err(e) = journal.create() {
+        roll_back_files(&moved);
+        return Err(e);
+    }
+
+    let mut written = Vec::new();
+
+    for (folder, doc) in &mut docs {
+        if !doc.xmp.is_dirty() {
+            continue;
+        }
+
+        let result = match doc.snapshot.has_changed(&doc.xmp_path) {
+            Ok(true) => Err(anyhow!(
+                "{} was modified by another program during the move",
+                doc.xmp_path.display()
+            )),
+            Ok(false) => write_metadata(&doc.xmp_path, &doc.xmp, args),
+            Err(e) => Err(e),
+        };
+
+        let result = result.and_then(|()| {
+            written.push((doc.xmp_path.clone(), doc.original.clone()));
+
+            journal.record(FolderChanges {
+                folder: folder.clone(),
+                items: journal::diff_items(&doc.before, &doc.xmp),
+            })
+        });
+
+        if let Err(e) = result {
+            roll_back_metadata(&written);
+            roll_back_files(&moved);
+
+            if let Err(e) = journal.discard() {
+                error!("Failed to delete journal for run {}: {}", journal.id, e);
+            }
+
+            return Err(e.context(format!(
+                "Failed to update metadata for {}",
+                folder.display()
+            )));
+        }
+
+        info!("Metadata updated for {}", folder.display());
+
+        report.add_folder(folder, changes.remove(folder).unwrap_or_default(), true);
+    }
+
+    // None of the moved files had any tags or colors to carry across.
+    if journal.folders.is_empty() {
+        journal.discard()?;
+        return report.output(args, None);
+    }
+
+    info!("Changes recorded as run {}", journal.id);
+
+    report.output(args, Some(journal.id))
+}
+
+/// Works out where each sample should end up, checking that the move can be carried
+/// out without overwriting anything.
+fn plan_moves(sources: &[PathBuf], dest: &Path) -> anyhow::Result<Vec<Move>> {
+    let dest_is_folder = dest.is_dir();
+
+    if sources.len() > 1 && !dest_is_folder {
+        bail!(
+            "{} is not a folder, so multiple files can't be moved into it",
+            dest.display()
+        );
+    }
+
+    let mut moves = Vec::with_capacity(sources.len());
+    let mut targets = HashSet::new();
+
+    for source in sources {
+        if !source.is_file() {
+            bail!("{} is not a file", source.display());
+        }
+
+        if !livemeta::is_supported_sample_format(source) {
+            bail!("{} doesn't look like an audio file", source.display());
+        }
+
+        let target = if dest_is_folder {
+            dest.join(source.file_name().context("Invalid source path")?)
+        } else {
+            dest.to_path_buf()
+        };
+
+        if !livemeta::is_supported_sample_format(&target) {
+            bail!("{} doesn't look like an audio file", target.display());
+        }
+
+        if livemeta::is_metadata(&target) {
+            bail!("{} is inside Ableton's metadata", target.display());
+        }
+
+        if target.exists() || sidecar_path(&target).exists() {
+            bail!("{} already exists", target.display());
+        }
+
+        let (from_folder, from_name) = split_path(source)?;
+        let (to_folder, to_name) = split_path(&target)?;
+
+        if !targets.insert((to_folder.clone(), to_name.clone())) {
+            bail!("Multiple files would be moved to {}", target.display());
+        }
+
+        moves.push(Move {
+            from_folder,
+            from_name,
+            to_folder,
+            to_name,
+        });
+    }
+
+    Ok(moves)
+}
+
+/// Splits a path into its canonical parent folder and its filename.
+///
+/// Canonicalizing the folder ensures that different spellings of the same folder
+/// share a single metadata document.
+fn split_path(path: &Path) -> anyhow::Result<(PathBuf, String)> {
+    let (Some(parent), Some(filename)) = (path.parent(), path.file_name().and_then(OsStr::to_str))
+    else {
+        bail!("Invalid path: {}", path.display());
+    };
+
+    let folder = canonicalize_folder(parent)
+        .with_context(|| format!("Folder does not exist: {}", parent.display()))?;
+
+    Ok((folder, filename.to_string()))
+}
+
+/// Loads a folder's metadata document, if it has one.
+fn load_doc(folder: &Path) -> anyhow::Result<FolderDoc> {
+    let xmp_path = livemeta::get_folder_metadata_path(folder);
+    let (original, snapshot) = Snapshot::read(&xmp_path)?;
+
+    let xmp = match &original {
+        Some(original) => FolderMetadata::from_xmp_str(original)?,
+        None => FolderMetadata::new()?,
+    };
+
+    let before = xmp.items().cloned().collect();
+
+    Ok(FolderDoc {
+        xmp_path,
+        xmp,
+        original,
+        snapshot,
+        before,
+    })
+}
+
+/// Returns the path of the analysis file Live creates alongside a sample.
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".asd");
+    PathBuf::from(sidecar)
+}
+
+/// Moves a sample and its analysis file, recording each completed step so that it
+/// can be rolled back.
+fn move_sample(from: &Path, to: &Path, moved: &mut Vec<(PathBuf, PathBuf)>) -> anyhow::Result<()> {
+    move_file(from, to)
+        .with_context(|| format!("Failed to move {} to {}", from.display(), to.display()))?;
+
+    moved.push((from.to_path_buf(), to.to_path_buf()));
+
+    let (from_sidecar, to_sidecar) = (sidecar_path(from), sidecar_path(to));
+
+    if from_sidecar.exists() {
+        move_file(&from_sidecar, &to_sidecar).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                from_sidecar.display(),
+                to_sidecar.display()
+            )
+        })?;
+
+        moved.push((from_sidecar, to_sidecar));
+    }
+
+    Ok(())
+}
+
+/// Moves a file, falling back to copying it if it is being moved to another device.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+
+            if let Err(e) = fs::remove_file(from) {
+                let _ = fs::remove_file(to);
+                return Err(e);
+            }
+
+            Ok(())
+        }
+
+        result => result,
+    }
+}
+
+/// Moves files back to where they came from, in reverse order.
+fn roll_back_files(moved: &[(PathBuf, PathBuf)]) {
+    for (from, to) in moved.iter().rev() {
+        match move_file(to, from) {
+            Ok(()) => info!("Moved {} back to {}", to.display(), from.display()),
+            Err(e) => error!(
+                "Failed to move {} back to {}: {}",
+                to.display(),
+                from.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Restores metadata documents to the contents they had before they were written.
+fn roll_back_metadata(written: &[(PathBuf, Option<String>)]) {
+    for (xmp_path, original) in written.iter().rev() {
+        let result = match original {
+            Some(original) => {
+                FolderMetadata::restore_xmp_file(xmp_path, original).map_err(anyhow::Error::from)
+            }
+            None => fs::remove_file(xmp_path).map_err(anyhow::Error::from),
+        };
+
+        match result {
+            Ok(()) => info!("Restored {}", xmp_path.display()),
+            Err(e) => error!("Failed to restore {}: {}", xmp_path.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) -> io::Result<()> {
+        fs::write(path, "")
+    }
+
+    #[test]
+    fn should_plan_moves() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = fs::canonicalize(dir.path())?;
+
+        fs::create_dir(root.join("Drums"))?;
+        fs::create_dir(root.join("Keys"))?;
+        touch(&root.join("Drums/bd1.wav"))?;
+        touch(&root.join("pad.wav"))?;
+
+        let moves = plan_moves(
+            &[root.join("Drums/bd1.wav"), root.join("pad.wav")],
+            &root.join("Keys"),
+        )?;
+
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].from_folder, root.join("Drums"));
+        assert_eq!(moves[0].target(), root.join("Keys/bd1.wav"));
+
+        // Renaming within a folder only touches that folder.
+        let moves = plan_moves(&[root.join("Drums/bd1.wav")], &root.join("Drums/kick.wav"))?;
+
+        assert_eq!(moves[0].from_folder, moves[0].to_folder);
+        assert_eq!(moves[0].to_name, "kick.wav");
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_unsafe_moves() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = fs::canonicalize(dir.path())?;
+
+        fs::create_dir(root.join("Drums"))?;
+        fs::create_dir(root.join("Keys"))?;
+        touch(&root.join("Drums/bd1.wav"))?;
+        touch(&root.join("Drums/bd2.wav"))?;
+        touch(&root.join("Drums/bd3.wav.asd"))?;
+        touch(&root.join("Keys/bd1.wav"))?;
+
+        let bd1 = root.join("Drums/bd1.wav");
+        let only_bd1 = [bd1.clone()];
+
+        // Missing sources.
+        assert!(plan_moves(&[root.join("Drums/bd4.wav")], &root.join("Keys")).is_err());
+
+        // Existing files, or their analysis files.
+        assert!(plan_moves(&only_bd1, &root.join("Drums/bd2.wav")).is_err());
+        assert!(plan_moves(&only_bd1, &root.join("Drums/bd3.wav")).is_err());
+        assert!(plan_moves(&only_bd1, &root.join("Keys")).is_err());
+
+        // Several files with the same name, or several files into one.
+        assert!(plan_moves(&[bd1.clone(), root.join("Keys/bd1.wav")], &root).is_err());
+        assert!(
+            plan_moves(
+                &[bd1.clone(), root.join("Drums/bd2.wav")],
+                &root.join("kick.wav")
+            )
+            .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_roll_back_moves() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        fs::create_dir(root.join("Drums"))?;
+        fs::create_dir(root.join("Keys"))?;
+        touch(&root.join("Drums/bd1.wav"))?;
+        touch(&root.join("Drums/bd1.wav.asd"))?;
+
+        let mut moved = Vec::new();
+
+        move_sample(
+            &root.join("Drums/bd1.wav"),
+            &root.join("Keys/bd1.wav"),
+            &mut moved,
+        )?;
+
+        assert_eq!(moved.len(), 2);
+        assert!(root.join("Keys/bd1.wav.asd").exists());
+
+        roll_back_files(&moved);
+
+        assert!(root.join("Drums/bd1.wav").exists());
+        assert!(root.join("Drums/bd1.wav.asd").exists());
+        assert!(!root.join("Keys/bd1.wav").exists());
+        assert!(!root.join("Keys/bd1.wav.asd").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_roll_back_metadata() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let initial = include_str!("test_data/initial.xml");
+
+        let existing = livemeta::get_folder_metadata_path(&dir.path().join("Drums"));
+        let created = livemeta::get_folder_metadata_path(&dir.path().join("Keys"));
+
+        let mut xmp = FolderMetadata::from_xmp_str(initial)?;
+        xmp.delete_item("bd1.wav");
+
+        xmp.save(&existing)?;
+        xmp.save(&created)?;
+
+        roll_back_metadata(&[
+            (existing.clone(), Some(initial.to_string())),
+            (created.clone(), None),
+        ]);
+
+        assert_eq!(fs::read_to_string(&existing)?, initial);
+        assert!(!created.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_journal_moves() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = fs::canonicalize(dir.path())?;
+
+        fs::create_dir(root.join("Drums"))?;
+        fs::create_dir(root.join("Keys"))?;
+        touch(&root.join("Drums/bd1.wav"))?;
+
+        let initial = FolderMetadata::from_xmp_str(include_str!("test_data/initial.xml"))?;
+        initial.save(&livemeta::get_folder_metadata_path(&root.join("Drums")))?;
+
+        move_samples(
+            &[root.join("Drums/bd1.wav")],
+            &root.join("Keys"),
+            &SaveArgs::committed(),
+        )?;
+
+        assert!(root.join("Keys/bd1.wav").exists());
+
+        let run = Journal::list()?
+            .into_iter()
+            .find(|j| j.folders.iter().any(|f| f.folder == root.join("Keys")))
+            .expect("move should be journaled");
+
+        let mut folders: Vec<_> = run.folders.iter().map(|f| &f.folder).collect();
+        folders.sort();
+
+        assert_eq!(folders, [&root.join("Drums"), &root.join("Keys")]);
+
+        let keys = run.folders.iter().find(|f| f.folder == root.join("Keys"));
+
+        assert_eq!(
+            keys.unwrap().items,
+            [ItemChanges {
+                file: "bd1.wav".into(),
+                keywords_added: vec!["Drums|Kick".into(), "Creator|17cupsofcoffee".into()],
+                colors_added: vec![1],
+                ..ItemChanges::default()
+            }]
+        );
+
+        Ok(())
+    }
+}
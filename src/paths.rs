@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use livemeta::Tag;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::{search_for_library_samples, template};
+
+/// The contents of a path mapping file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    #[serde(default)]
+    tags: Vec<String>,
+
+    #[serde(default)]
+    folders: HashMap<String, Vec<String>>,
+}
+
+/// A mapping from the folders a file is in to tags.
+#[derive(Debug)]
+pub struct PathTags {
+    /// Tags applied to every file, with placeholders for the folder names.
+    templates: Vec<String>,
+
+    /// Tags applied to files inside a folder with a given name, keyed by the
+    /// lowercased name.
+    folders: HashMap<String, Vec<String>>,
+}
+
+impl PathTags {
+    /// Parses a mapping file, in TOML format.
+    ///
+    /// `tags` is a list of tags to apply to every file, which can contain
+    /// `{component0}`, `{component1}`, etc. placeholders for the folders below the
+    /// root. `folders` is a table of folder names, and the tags to apply to any
+    /// file inside a folder with that name (at any depth).
+    pub fn parse(data: &str) -> anyhow::Result<PathTags> {
+        let file: MappingFile = toml::from_str(data)?;
+
+        if file.tags.is_empty() && file.folders.is_empty() {
+            bail!("The mapping must contain some tags or folders");
+        }
+
+        for template in &file.tags {
+            check_template(template).with_context(|| format!("Invalid tag: {template}"))?;
+        }
+
+        let mut folders = HashMap::new();
+
+        for (name, tags) in file.folders {
+            for template in &tags {
+                check_template(template)
+                    .with_context(|| format!("Invalid tag for folder {name}: {template}"))?;
+            }
+
+            folders
+                .entry(name.to_lowercase())
+                .or_insert_with(Vec::new)
+                .extend(tags);
+        }
+
+        Ok(PathTags {
+            templates: file.tags,
+            folders,
+        })
+    }
+
+    /// Returns the tags for a file, given its folder relative to the root.
+    ///
+    /// Tags referring to components deeper than the folder are skipped.
+    pub fn tags_for(&self, folder: &Path) -> Vec<Tag> {
+        let components: Vec<_> = folder
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .collect();
+
+        let folder_tags = components
+            .iter()
+            .filter_map(|c| self.folders.get(&c.to_lowercase()))
+            .flatten();
+
+        let mut tags = Vec::new();
+
+        for template in self.templates.iter().chain(folder_tags) {
+            let value = template::expand(template, |name| {
+                let index = component_index(name)?;
+                components.get(index).map(String::as_str)
+            });
+
+            let Some(value) = value else {
+                continue;
+            };
+
+            match Tag::parse(&value) {
+                Ok(tag) if !tags.contains(&tag) => tags.push(tag),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping {} for {}: {}", template, folder.display(), e),
+            }
+        }
+
+        tags
+    }
+
+    /// Finds the tags for every sample under a root folder, grouped by folder.
+    ///
+    /// Samples in folders that don't have any tags are left out, as are files and
+    /// folders matching the exclude globs or the root's ignore file.
+    pub fn match_samples(
+        &self,
+        root: &Path,
+        excludes: &[String],
+    ) -> anyhow::Result<HashMap<PathBuf, HashMap<String, Vec<Tag>>>> {
+        let mut matched = HashMap::new();
+
+        for (folder, files) in search_for_library_samples(root, excludes)? {
+            let relative = folder.strip_prefix(root).unwrap_or(&folder);
+            let tags = self.tags_for(relative);
+
+            if tags.is_empty() {
+                continue;
+            }
+
+            let files = files.into_iter().map(|file| (file, tags.clone())).collect();
+
+            matched.insert(folder, files);
+        }
+
+        Ok(matched)
+    }
+}
+
+/// Logs the tags derived for each sample, sorted by path.
+pub fn log_derived_tags(matched: &HashMap<PathBuf, HashMap<String, Vec<Tag>>>) {
+    let mut paths: Vec<(PathBuf, &Vec<Tag>)> = matched
+        .iter()
+        .flat_map(|(folder, files)| files.iter().map(|(file, tags)| (folder.join(file), tags)))
+        .collect();
+
+    paths.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (path, tags) in paths {
+        let tags: Vec<&str> = tags.iter().map(Tag::as_str).collect();
+
+        info!("Derived tags for {}: {}", path.display(), tags.join(", "));
+    }
+}
+
+/// Checks that a tag is valid, and only refers to path components.
+fn check_template(template: &str) -> anyhow::Result<()> {
+    for name in template::placeholders(template) {
+        if component_index(name).is_none() {
+            bail!("Unknown placeholder {{{name}}}, expected e.g. {{component0}}");
+        }
+    }
+
+    // Placeholders are swapped out for a dummy value, so the rest of the tag
+    // can be validated up front.
+    let example = template::expand(template, |_| Some("x")).unwrap_or_default();
+    Tag::parse(&example)?;
+
+    Ok(())
+}
+
+fn component_index(name: &str) -> Option<usize> {
+    name.strip_prefix("component")?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPPING: &str = r#"
+tags = ["Creator|{component0}", "Pack|{component0}|{component1}"]
+
+[folders]
+Kicks = ["Drums|Kick"]
+"One Shots" = ["One Shot"]
+"#;
+
+    fn tags_for(mapping: &PathTags, folder: &str) -> Vec<String> {
+        mapping
+            .tags_for(Path::new(folder))
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn should_map_path_components() -> anyhow::Result<()> {
+        let mapping = PathTags::parse(MAPPING)?;
+
+        assert_eq!(
+            tags_for(&mapping, "Vendor/Pack/Drums/kicks"),
+            ["Creator|Vendor", "Pack|Vendor|Pack", "Drums|Kick"]
+        );
+        assert_eq!(
+            tags_for(&mapping, "Vendor/Kicks/One Shots"),
+            [
+                "Creator|Vendor",
+                "Pack|Vendor|Kicks",
+                "Drums|Kick",
+                "One Shot"
+            ]
+        );
+        assert_eq!(tags_for(&mapping, "Vendor"), ["Creator|Vendor"]);
+        assert!(tags_for(&mapping, "").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_invalid_mappings() {
+        for mapping in [
+            "",
+            "tags = [\"Creator|{vendor}\"]",
+            "tags = [\"Creator||{component0}\"]",
+            "[folders]\nKicks = [\"Drums||Kick\"]",
+            "tag = [\"Drums\"]",
+        ] {
+            assert!(
+                PathTags::parse(mapping).is_err(),
+                "{mapping:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn should_match_samples_under_root() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        std::fs::create_dir_all(root.join("Vendor/Kicks"))?;
+        std::fs::create_dir_all(root.join("Vendor/Old"))?;
+        std::fs::write(root.join("Vendor/Kicks/Kick 01.wav"), "")?;
+        std::fs::write(root.join("Vendor/Kicks/Kick 02 Demo.wav"), "")?;
+        std::fs::write(root.join("Vendor/Old/Kick 01.wav"), "")?;
+        std::fs::write(root.join("Root.wav"), "")?;
+
+        let mapping = PathTags::parse(MAPPING)?;
+        let matched =
+            mapping.match_samples(root, &["**/Old".to_string(), "**/*Demo*".to_string()])?;
+
+        // Samples in the root don't have any folder names to derive tags from.
+        assert_eq!(matched.len(), 1);
+
+        let files = &matched[&root.join("Vendor/Kicks")];
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files["Kick 01.wav"],
+            [
+                Tag::parse("Creator|Vendor")?,
+                Tag::parse("Pack|Vendor|Kicks")?,
+                Tag::parse("Drums|Kick")?
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use livemeta::FolderMetadata;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::changes::{ChangeSet, ItemChanges};
+use crate::{SaveArgs, process_folders_with_hashes};
+
+/// A set of changes that has been planned, but not yet applied.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// The arguments LiveTagger was run with to create the plan.
+    pub command: Vec<String>,
+
+    pub folders: Vec<PlannedFolder>,
+}
+
+/// The changes planned for a single folder's metadata.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedFolder {
+    pub folder: PathBuf,
+
+    /// The SHA-256 hash of the folder's metadata file when the plan was made, or
+    /// `None` if it didn't exist yet.
+    pub hash: Option<String>,
+
+    pub items: Vec<ItemChanges>,
+}
+
+impl Plan {
+    /// Creates an empty plan for the current run.
+    pub fn new() -> Plan {
+        Plan {
+            command: env::args().skip(1).collect(),
+            folders: Vec::new(),
+        }
+    }
+
+    /// Loads a plan from a file.
+    pub fn load(path: &Path) -> anyhow::Result<Plan> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Could not read {}", path.display()))?;
+
+        let loaded: Plan = serde_json::from_str(&data)
+            .with_context(|| format!("Invalid plan: {}", path.display()))?;
+
+        // Older plans could list the same folder more than once.
+        let mut plan = Plan {
+            command: loaded.command,
+            folders: Vec::new(),
+        };
+
+        for planned in loaded.folders {
+            plan.add_folder(planned)
+                .with_context(|| format!("Invalid plan: {}", path.display()))?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Adds the changes planned for a folder, merging them with any changes that are
+    /// already planned for it.
+    pub fn add_folder(&mut self, planned: PlannedFolder) -> anyhow::Result<()> {
+        let Some(existing) = self.folders.iter_mut().find(|f| f.folder == planned.folder) else {
+            self.folders.push(planned);
+            return Ok(());
+        };
+
+        if existing.hash != planned.hash {
+            bail!(
+                "{} is listed more than once, with different hashes",
+                planned.folder.display()
+            );
+        }
+
+        existing.items.extend(planned.items);
+
+        Ok(())
+    }
+
+    /// Writes the plan to a file.
+    ///
+    /// The folders and items are sorted first, so that planning the same changes
+    /// twice produces the same file.
+    pub fn save(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.folders.sort_by(|a, b| a.folder.cmp(&b.folder));
+
+        for planned in &mut self.folders {
+            // This is a stable sort, so changes to the same file stay in order.
+            planned.items.sort_by(|a, b| a.file.cmp(&b.file));
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Could not write {}", path.display()))
+    }
+}
+
+/// Returns the SHA-256 hash of a metadata file's contents, as a hex string.
+pub fn hash_contents(data: &str) -> String {
+    format!("{:x}", Sha256::digest(data.as_bytes()))
+}
+
+/// Returns the hash of a folder's metadata file, or `None` if it doesn't exist.
+pub fn hash_folder_metadata(folder: &Path) -> anyhow::Result<Option<String>> {
+    let xmp_path = livemeta::get_folder_metadata_path(folder);
+
+    if !xmp_path.exists() {
+        return Ok(None);
+    }
+
+    let data = fs::read_to_string(&xmp_path)
+        .with_context(|| format!("Could not read {}", xmp_path.display()))?;
+
+    Ok(Some(hash_contents(&data)))
+}
+
+/// Applies a set of planned changes to a document.
+pub fn apply_changes(doc: &mut FolderMetadata, changes: &[ItemChanges]) -> ChangeSet {
+    let mut applied = ChangeSet::default();
+
+    for change in changes {
+        if change.entry_removed {
+            if let Some(item) = doc.delete_item(&change.file) {
+                info!("Removing entry for {}", change.file);
+
+                applied.push(ItemChanges {
+                    file: change.file.clone(),
+                    keywords_removed: item.keywords,
+                    colors_removed: item.colors,
+                    entry_removed: true,
+                    ..ItemChanges::default()
+                });
+            }
+
+            continue;
+        }
+
+        let needs_item = !change.keywords_added.is_empty() || !change.colors_added.is_empty();
+
+        let item = if needs_item {
+            doc.get_or_insert_item(&change.file)
+        } else {
+            match doc.find_item_mut(&change.file) {
+                Some(item) => item,
+                None => continue,
+            }
+        };
+
+        let mut log = Vec::new();
+        let mut item_changes = ItemChanges {
+            file: change.file.clone(),
+            ..ItemChanges::default()
+        };
+
+        for keyword in &change.keywords_removed {
+            if item.remove_keyword(keyword) {
+                log.push(format!("-{keyword}"));
+                item_changes.keywords_removed.push(keyword.clone());
+            }
+        }
+
+        for keyword in &change.keywords_added {
+            if item.add_keyword(keyword.as_str()) {
+                log.push(format!("+{keyword}"));
+                item_changes.keywords_added.push(keyword.clone());
+            }
+        }
+
+        for &color in &change.colors_removed {
+            if item.remove_color(color) {
+                log.push(format!("-color {color}"));
+                item_changes.colors_removed.push(color);
+            }
+        }
+
+        for &color in &change.colors_added {
+            if item.add_color(color) {
+                log.push(format!("+color {color}"));
+                item_changes.colors_added.push(color);
+            }
+        }
+
+        if !log.is_empty() {
+            info!("Applying changes to {}: {}", change.file, log.join(", "));
+        }
+
+        applied.push(item_changes);
+    }
+
+    applied
+}
+
+/// Applies the changes from a plan file.
+///
+/// If any folder's metadata has changed since the plan was made, nothing is applied.
+pub fn apply_plan(path: &Path, save: &SaveArgs) -> anyhow::Result<()> {
+    let plan = Plan::load(path)?;
+
+    info!("Applying plan from: livetagger {}", plan.command.join(" "));
+
+    let mut changed = Vec::new();
+
+    for planned in &plan.folders {
+        if hash_folder_metadata(&planned.folder)? != planned.hash {
+            changed.push(planned.folder.display().to_string());
+        }
+    }
+
+    if !changed.is_empty() {
+        bail!(
+            "The metadata for these folders has changed since the plan was made, so it can't be applied safely:\n{}",
+            changed.join("\n")
+        );
+    }
+
+    let folders = plan
+        .folders
+        .iter()
+        .map(|f| (f.folder.clone(), Default::default()))
+        .collect();
+
+    let changes: HashMap<&Path, &[ItemChanges]> = plan
+        .folders
+        .iter()
+        .map(|f| (f.folder.as_path(), f.items.as_slice()))
+        .collect();
+
+    // The hashes are checked again as each folder is read, in case anything changes
+    // while the plan is being applied.
+    let hashes = plan
+        .folders
+        .iter()
+        .map(|f| (f.folder.clone(), f.hash.clone()))
+        .collect();
+
+    process_folders_with_hashes(save, folders, &hashes, |folder, doc, _| {
+        Ok(match changes.get(folder) {
+            Some(changes) => apply_changes(doc, changes),
+            None => ChangeSet::default(),
+        })
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn should_apply_changes() -> anyhow::Result<()> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+
+        let changes = [
+            ItemChanges {
+                file: "bd1.wav".into(),
+                keywords_added: vec!["Loop".into()],
+                keywords_removed: vec!["Drums|Kick".into()],
+                colors_added: vec![2],
+                colors_removed: vec![1],
+                ..ItemChanges::default()
+            },
+            ItemChanges {
+                file: "bd2.wav".into(),
+                entry_removed: true,
+                ..ItemChanges::default()
+            },
+            ItemChanges {
+                file: "bd3.wav".into(),
+                keywords_removed: vec!["Drums|Kick".into()],
+                ..ItemChanges::default()
+            },
+        ];
+
+        let applied = apply_changes(&mut meta, &changes);
+
+        let bd1 = meta.find_item("bd1.wav").unwrap();
+
+        assert_eq!(bd1.keywords, ["Creator|17cupsofcoffee", "Loop"]);
+        assert_eq!(bd1.colors, [2]);
+        assert!(meta.find_item("bd2.wav").is_none());
+        assert!(meta.find_item("bd3.wav").is_none());
+
+        assert_eq!(applied.len(), 2);
+        assert_eq!(
+            applied.items[1].keywords_removed,
+            ["Creator|17cupsofcoffee"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_merge_duplicate_folders() -> anyhow::Result<()> {
+        let mut plan = Plan {
+            command: Vec::new(),
+            folders: Vec::new(),
+        };
+
+        for file in ["bd1.wav", "bd2.wav"] {
+            plan.add_folder(PlannedFolder {
+                folder: "/samples/Drums".into(),
+                hash: Some("abc".into()),
+                items: vec![ItemChanges {
+                    file: file.into(),
+                    keywords_added: vec!["X".into()],
+                    ..ItemChanges::default()
+                }],
+            })?;
+        }
+
+        assert_eq!(plan.folders.len(), 1);
+        assert_eq!(plan.folders[0].items.len(), 2);
+
+        let conflicting = plan.add_folder(PlannedFolder {
+            folder: "/samples/Drums".into(),
+            hash: None,
+            items: Vec::new(),
+        });
+
+        assert!(conflicting.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_save_sorted_plans() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let planned = |folder: &str, files: &[&str]| PlannedFolder {
+            folder: folder.into(),
+            hash: None,
+            items: files
+                .iter()
+                .map(|file| ItemChanges {
+                    file: file.to_string(),
+                    keywords_added: vec!["Loop".into()],
+                    ..ItemChanges::default()
+                })
+                .collect(),
+        };
+
+        let mut plan = Plan {
+            command: Vec::new(),
+            folders: vec![
+                planned("Snares", &["sd2.wav", "sd1.wav"]),
+                planned("Kicks", &["bd1.wav"]),
+            ],
+        };
+
+        let plan_path = dir.path().join("plan.json");
+        plan.save(&plan_path)?;
+
+        let saved = Plan::load(&plan_path)?;
+
+        let folders: Vec<_> = saved.folders.iter().map(|f| f.folder.clone()).collect();
+        let files: Vec<_> = saved.folders[1]
+            .items
+            .iter()
+            .map(|i| i.file.clone())
+            .collect();
+
+        assert_eq!(folders, [PathBuf::from("Kicks"), PathBuf::from("Snares")]);
+        assert_eq!(files, ["sd1.wav", "sd2.wav"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_refuse_changed_metadata() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let folder = fs::canonicalize(dir.path())?;
+
+        let xmp_path = livemeta::get_folder_metadata_path(&folder);
+        fs::create_dir_all(xmp_path.parent().unwrap())?;
+
+        let initial = include_str!("test_data/initial.xml");
+        fs::write(&xmp_path, initial)?;
+
+        let mut plan = Plan {
+            command: Vec::new(),
+            folders: vec![PlannedFolder {
+                folder: folder.clone(),
+                hash: Some(hash_contents(initial)),
+                items: vec![ItemChanges {
+                    file: "bd1.wav".into(),
+                    keywords_added: vec!["Loop".into()],
+                    ..ItemChanges::default()
+                }],
+            }],
+        };
+
+        let plan_path = dir.path().join("plan.json");
+        plan.save(&plan_path)?;
+
+        let edited = initial.replace("bd2.wav", "bd3.wav");
+        fs::write(&xmp_path, &edited)?;
+
+        let save = SaveArgs::committed();
+
+        let error = apply_plan(&plan_path, &save).unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("changed since the plan was made")
+        );
+        assert_eq!(fs::read_to_string(&xmp_path)?, edited);
+
+        // Changes made after the up-front check are caught when each folder is read.
+        let folders = HashMap::from([(folder.clone(), HashSet::new())]);
+        let hashes = HashMap::from([(folder.clone(), Some(hash_contents(initial)))]);
+
+        let result = process_folders_with_hashes(&save, folders, &hashes, |_, doc, _| {
+            Ok(apply_changes(doc, &plan.folders[0].items))
+        });
+
+        let error = result.unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("changed since the plan was made")
+        );
+        assert_eq!(fs::read_to_string(&xmp_path)?, edited);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_hash_contents() {
+        assert_eq!(
+            hash_contents("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
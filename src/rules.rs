@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use glob::{MatchOptions, Pattern};
+use livemeta::Tag;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::search_for_library_samples;
+
+/// The contents of a rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleDef>,
+}
+
+/// A rule, as written in a rules file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    glob: Option<String>,
+    regex: Option<String>,
+    tags: Vec<String>,
+}
+
+/// A set of rules for tagging files based on their paths.
+#[derive(Debug)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    matcher: Matcher,
+    tags: Vec<Tag>,
+}
+
+#[derive(Debug)]
+enum Matcher {
+    /// Matches against the whole relative path, or just the filename if the
+    /// pattern doesn't contain a `/`.
+    Glob {
+        pattern: Pattern,
+        filename_only: bool,
+    },
+
+    /// Matches anywhere within the relative path.
+    Regex(Regex),
+}
+
+impl Rules {
+    /// Parses a rules file, in TOML format.
+    ///
+    /// Each rule is a `[[rule]]` table, with either a `glob` or a `regex` pattern
+    /// and a list of `tags` to apply to the files that match it.
+    pub fn parse(data: &str) -> anyhow::Result<Rules> {
+        let file: RulesFile = toml::from_str(data)?;
+
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, def)| Rule::new(def).with_context(|| format!("Invalid rule {}", i + 1)))
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Rules { rules })
+    }
+
+    /// Returns the tags of every rule matching a path, relative to the root folder.
+    ///
+    /// Tags are returned in the order the rules are defined, without duplicates.
+    pub fn tags_for(&self, path: &Path) -> Vec<Tag> {
+        // Paths are always matched with forward slashes, so that rules files
+        // work the same on every platform.
+        let path = path
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut tags = Vec::new();
+
+        for rule in &self.rules {
+            if !rule.matcher.matches(&path) {
+                continue;
+            }
+
+            for tag in &rule.tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+
+        tags
+    }
+
+    /// Finds the tags for every sample under a root folder, grouped by folder.
+    ///
+    /// Samples that don't match any rules are left out, as are files and folders
+    /// matching the exclude globs or the root's ignore file.
+    pub fn match_samples(
+        &self,
+        root: &Path,
+        excludes: &[String],
+    ) -> anyhow::Result<HashMap<PathBuf, HashMap<String, Vec<Tag>>>> {
+        let mut matched: HashMap<PathBuf, HashMap<String, Vec<Tag>>> = HashMap::new();
+
+        for (folder, files) in search_for_library_samples(root, excludes)? {
+            let relative = folder.strip_prefix(root).unwrap_or(&folder);
+
+            for file in files {
+                let tags = self.tags_for(&relative.join(&file));
+
+                if !tags.is_empty() {
+                    matched
+                        .entry(folder.clone())
+                        .or_default()
+                        .insert(file, tags);
+                }
+            }
+        }
+
+        Ok(matched)
+    }
+}
+
+impl Rule {
+    fn new(def: RuleDef) -> anyhow::Result<Rule> {
+        let matcher = match (def.glob, def.regex) {
+            (Some(glob), None) => Matcher::Glob {
+                filename_only: !glob.contains('/'),
+                pattern: Pattern::new(&glob)?,
+            },
+
+            (None, Some(regex)) => Matcher::Regex(Regex::new(&regex)?),
+
+            _ => bail!("A rule must have either a glob or a regex"),
+        };
+
+        if def.tags.is_empty() {
+            bail!("A rule must have at least one tag");
+        }
+
+        let tags = def
+            .tags
+            .iter()
+            .map(|tag| Tag::parse(tag))
+            .collect::<livemeta::Result<_>>()?;
+
+        Ok(Rule { matcher, tags })
+    }
+}
+
+impl Matcher {
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Matcher::Glob {
+                pattern,
+                filename_only,
+            } => {
+                let target = if *filename_only {
+                    path.rsplit('/').next().unwrap_or(path)
+                } else {
+                    path
+                };
+
+                pattern.matches_with(
+                    target,
+                    MatchOptions {
+                        require_literal_separator: true,
+                        ..MatchOptions::new()
+                    },
+                )
+            }
+
+            Matcher::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+[[rule]]
+glob = "*Kick*"
+tags = ["Drums|Kick"]
+
+[[rule]]
+glob = "Vendor/**/*.wav"
+tags = ["Creator|Vendor", "Drums|Kick"]
+
+[[rule]]
+regex = "(?i)_\\d+bpm_"
+tags = ["Loop"]
+"#;
+
+    fn tags_for(rules: &Rules, path: &str) -> Vec<String> {
+        rules
+            .tags_for(Path::new(path))
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn should_match_rules() -> anyhow::Result<()> {
+        let rules = Rules::parse(RULES)?;
+
+        assert_eq!(tags_for(&rules, "Drums/Kick 01.wav"), ["Drums|Kick"]);
+        assert_eq!(
+            tags_for(&rules, "Vendor/Pack/Kick 01.wav"),
+            ["Drums|Kick", "Creator|Vendor"]
+        );
+        assert_eq!(
+            tags_for(&rules, "Vendor/Pack/Loop_124BPM_Amin.wav"),
+            ["Creator|Vendor", "Drums|Kick", "Loop"]
+        );
+        assert!(tags_for(&rules, "Kicks/Snare.wav").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_invalid_rules() {
+        for rules in [
+            "[[rule]]\ntags = [\"Drums\"]",
+            "[[rule]]\nglob = \"*\"\nregex = \".*\"\ntags = [\"Drums\"]",
+            "[[rule]]\nglob = \"*\"\ntags = []",
+            "[[rule]]\nglob = \"*\"\ntags = [\"Drums||Kick\"]",
+            "[[rule]]\nregex = \"(\"\ntags = [\"Drums\"]",
+            "[[rule]]\nglob = \"*\"\ntag = [\"Drums\"]",
+        ] {
+            assert!(Rules::parse(rules).is_err(), "{rules:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn should_match_samples_under_root() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path();
+
+        std::fs::create_dir_all(root.join("Vendor/Pack"))?;
+        std::fs::create_dir_all(root.join("Backup"))?;
+        std::fs::write(root.join("Vendor/Pack/Kick 01.wav"), "")?;
+        std::fs::write(root.join("Vendor/Pack/Kick 02 Demo.wav"), "")?;
+        std::fs::write(root.join("Vendor/Pack/notes.txt"), "")?;
+        std::fs::write(root.join("Backup/Kick 01.wav"), "")?;
+
+        let rules = Rules::parse(RULES)?;
+        let matched =
+            rules.match_samples(root, &["**/Backup".to_string(), "**/*Demo*".to_string()])?;
+
+        assert_eq!(matched.len(), 1);
+
+        let files = &matched[&root.join("Vendor/Pack")];
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files["Kick 01.wav"],
+            [Tag::parse("Drums|Kick")?, Tag::parse("Creator|Vendor")?]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::Context;
+
+use crate::plan;
+
+/// The state of a metadata file when it was read, so that we can tell if something
+/// else (e.g. Live re-indexing the folder) changes it before we write to it.
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    modified: Option<SystemTime>,
+    hash: Option<String>,
+}
+
+impl Snapshot {
+    /// Reads a metadata file, returning its contents (or `None` if it doesn't exist)
+    /// along with a snapshot of its state.
+    pub fn read(xmp_path: &Path) -> anyhow::Result<(Option<String>, Snapshot)> {
+        if !xmp_path.exists() {
+            return Ok((
+                None,
+                Snapshot {
+                    modified: None,
+                    hash: None,
+                },
+            ));
+        }
+
+        // The modification time is read first, so that a write which lands between
+        // the two reads shows up as a change rather than being missed.
+        let modified = modified_time(xmp_path)?;
+
+        let data = fs::read_to_string(xmp_path)
+            .with_context(|| format!("Could not read {}", xmp_path.display()))?;
+
+        let snapshot = Snapshot {
+            modified,
+            hash: Some(plan::hash_contents(&data)),
+        };
+
+        Ok((Some(data), snapshot))
+    }
+
+    /// Returns the SHA-256 hash of the file's contents, or `None` if it didn't exist.
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    /// Returns whether the metadata file has changed since the snapshot was taken.
+    ///
+    /// Both the modification time and the contents are checked, as modification
+    /// times aren't always precise enough to catch writes made in quick succession.
+    pub fn has_changed(&self, xmp_path: &Path) -> anyhow::Result<bool> {
+        let (_, current) = Snapshot::read(xmp_path)?;
+
+        Ok(current != *self)
+    }
+}
+
+fn modified_time(path: &Path) -> anyhow::Result<Option<SystemTime>> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("Could not read {}", path.display()))?;
+
+    // Not every platform records modification times, in which case we fall back to
+    // only comparing the contents.
+    Ok(metadata.modified().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn should_detect_changes() -> anyhow::Result<()> {
+        let path = env::temp_dir().join(format!("livetagger-snapshot-{}.xmp", process::id()));
+
+        let (original, snapshot) = Snapshot::read(&path)?;
+
+        assert_eq!(original, None);
+        assert!(!snapshot.has_changed(&path)?);
+
+        fs::write(&path, "<a/>")?;
+
+        assert!(snapshot.has_changed(&path)?);
+
+        let (original, snapshot) = Snapshot::read(&path)?;
+
+        assert_eq!(original.as_deref(), Some("<a/>"));
+        assert!(!snapshot.has_changed(&path)?);
+
+        fs::write(&path, "<b/>")?;
+
+        let changed = snapshot.has_changed(&path);
+        fs::remove_file(&path)?;
+
+        assert!(changed?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use livemeta::{FolderMetadata, TAG_SEPARATOR};
+use serde::Serialize;
+
+use crate::{csv, list_sample_files};
+
+/// The output formats supported when reporting tag statistics.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum StatsFormat {
+    /// A human-readable report.
+    #[default]
+    Plain,
+
+    /// A JSON object.
+    Json,
+
+    /// CSV rows of `kind,name,count`, where `kind` is either `tag` or `untagged`.
+    Csv,
+}
+
+/// Statistics about the tags used across a set of folders.
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// The number of audio files found.
+    pub files: usize,
+
+    /// The number of audio files with at least one tag.
+    pub tagged_files: usize,
+
+    /// The number of files using each tag, most used first.
+    pub tags: Vec<TagCount>,
+
+    /// The tag hierarchy, where each level counts the files that use it or any of
+    /// the tags nested within it.
+    pub hierarchy: Vec<TagNode>,
+
+    /// The number of untagged audio files in each folder that has any.
+    pub untagged: Vec<FolderCount>,
+}
+
+/// The number of files using a tag.
+#[derive(Debug, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// A level of the tag hierarchy, e.g. `Kick` within `Drums`.
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    /// The name of this level, without its parents.
+    pub name: String,
+
+    /// The number of files using this tag, or any of the tags nested within it.
+    pub count: usize,
+
+    pub children: Vec<TagNode>,
+}
+
+/// The number of untagged audio files in a folder.
+#[derive(Debug, Serialize)]
+pub struct FolderCount {
+    pub folder: PathBuf,
+    pub count: usize,
+}
+
+/// Accumulates statistics folder by folder.
+#[derive(Debug, Default)]
+pub struct StatsBuilder {
+    files: usize,
+    tagged_files: usize,
+    tags: HashMap<String, usize>,
+
+    /// The number of files using each level of the hierarchy, keyed by the parts of
+    /// the tag so that each level sorts directly after its parent.
+    hierarchy: BTreeMap<Vec<String>, usize>,
+    untagged: Vec<FolderCount>,
+}
+
+impl StatsBuilder {
+    /// Adds a folder's metadata (if it has any) and the audio files within it.
+    ///
+    /// Tags are counted for every entry in the metadata, but only the files that
+    /// exist are checked for being untagged.
+    pub fn add_folder(&mut self, folder: &Path, doc: Option<&FolderMetadata>, files: &[String]) {
+        let mut untagged = 0;
+
+        for file in files {
+            let tagged = doc
+                .and_then(|doc| doc.find_item(file))
+                .is_some_and(|item| !item.keywords.is_empty());
+
+            self.files += 1;
+
+            if tagged {
+                self.tagged_files += 1;
+            } else {
+                untagged += 1;
+            }
+        }
+
+        if untagged > 0 {
+            self.untagged.push(FolderCount {
+                folder: folder.to_path_buf(),
+                count: untagged,
+            });
+        }
+
+        for item in doc.into_iter().flat_map(FolderMetadata::items) {
+            let mut levels = BTreeSet::new();
+
+            for keyword in &item.keywords {
+                *self.tags.entry(keyword.clone()).or_default() += 1;
+
+                levels.insert(keyword.as_str());
+                levels.extend(
+                    keyword
+                        .match_indices(TAG_SEPARATOR)
+                        .map(|(i, _)| &keyword[..i]),
+                );
+            }
+
+            // Each file is only counted once per level, even if it has several tags
+            // nested within it.
+            for level in levels {
+                let parts = level.split(TAG_SEPARATOR).map(String::from).collect();
+                *self.hierarchy.entry(parts).or_default() += 1;
+            }
+        }
+    }
+
+    /// Finishes collecting, sorting the results.
+    pub fn build(self) -> Stats {
+        let mut tags: Vec<TagCount> = self
+            .tags
+            .into_iter()
+            .map(|(tag, count)| TagCount { tag, count })
+            .collect();
+
+        tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+
+        let mut untagged = self.untagged;
+        untagged.sort_by(|a, b| a.folder.cmp(&b.folder));
+
+        Stats {
+            files: self.files,
+            tagged_files: self.tagged_files,
+            tags,
+            hierarchy: build_tree(self.hierarchy),
+            untagged,
+        }
+    }
+}
+
+/// Builds the tag tree from the counts for each level of the hierarchy, with each
+/// level's children in alphabetical order.
+///
+/// As the levels are sorted by their parts, each one is visited straight after its
+/// parent and siblings, so the tree can be built in a single pass.
+fn build_tree(levels: BTreeMap<Vec<String>, usize>) -> Vec<TagNode> {
+    let mut roots = Vec::new();
+
+    // The ancestors of the current level, from the top of the hierarchy down.
+    let mut open: Vec<(Vec<String>, TagNode)> = Vec::new();
+
+    for (parts, count) in levels {
+        let depth = parts.len() - 1;
+
+        while open.len() > depth {
+            close_level(&mut open, &mut roots);
+        }
+
+        // Levels whose parent is missing can't be placed in the tree.
+        if open.len() != depth || open.last().is_some_and(|(p, _)| p[..] != parts[..depth]) {
+            continue;
+        }
+
+        let node = TagNode {
+            name: parts[depth].clone(),
+            count,
+            children: Vec::new(),
+        };
+
+        open.push((parts, node));
+    }
+
+    while !open.is_empty() {
+        close_level(&mut open, &mut roots);
+    }
+
+    roots
+}
+
+/// Finishes the deepest open level, adding it to its parent (or the roots).
+fn close_level(open: &mut Vec<(Vec<String>, TagNode)>, roots: &mut Vec<TagNode>) {
+    let Some((_, node)) = open.pop() else {
+        return;
+    };
+
+    match open.last_mut() {
+        Some((_, parent)) => parent.children.push(node),
+        None => roots.push(node),
+    }
+}
+
+/// Gathers statistics for a set of folders.
+pub fn collect_stats(folders: &[PathBuf]) -> anyhow::Result<Stats> {
+    let mut builder = StatsBuilder::default();
+
+    for folder in folders {
+        let xmp_path = livemeta::get_folder_metadata_path(folder);
+
+        let doc = if xmp_path.exists() {
+            Some(FolderMetadata::from_xmp_file(&xmp_path)?)
+        } else {
+            None
+        };
+
+        let files = list_sample_files(folder)?;
+
+        builder.add_folder(folder, doc.as_ref(), &files);
+    }
+
+    Ok(builder.build())
+}
+
+/// Renders tag statistics in the given format.
+pub fn render(stats: &Stats, format: StatsFormat) -> anyhow::Result<String> {
+    match format {
+        StatsFormat::Plain => Ok(render_plain(stats)),
+        StatsFormat::Json => Ok(serde_json::to_string_pretty(stats)? + "\n"),
+        StatsFormat::Csv => Ok(render_csv(stats)),
+    }
+}
+
+fn render_plain(stats: &Stats) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "Files: {} ({} tagged, {} untagged)",
+        stats.files,
+        stats.tagged_files,
+        stats.files - stats.tagged_files
+    );
+
+    if !stats.tags.is_empty() {
+        let width = stats.tags[0].count.to_string().len();
+
+        out.push_str("\nTags:\n");
+
+        for tag in &stats.tags {
+            let _ = writeln!(out, "  {:>width$}  {}", tag.count, tag.tag);
+        }
+
+        out.push_str("\nHierarchy:\n");
+        render_tree(&mut out, &stats.hierarchy, "");
+    }
+
+    if !stats.untagged.is_empty() {
+        out.push_str("\nUntagged files:\n");
+
+        let width = stats
+            .untagged
+            .iter()
+            .map(|f| f.count.to_string().len())
+            .max()
+            .unwrap_or_default();
+
+        for folder in &stats.untagged {
+            let path = folder.folder.display().to_string();
+            let path = if path.is_empty() { "." } else { &path };
+
+            let _ = writeln!(out, "  {:>width$}  {}", folder.count, path);
+        }
+    }
+
+    out
+}
+
+fn render_tree(out: &mut String, nodes: &[TagNode], indent: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let (branch, child_indent) = if i + 1 == nodes.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+
+        let _ = writeln!(out, "{indent}{branch}{} ({})", node.name, node.count);
+
+        render_tree(out, &node.children, &format!("{indent}{child_indent}"));
+    }
+}
+
+fn render_csv(stats: &Stats) -> String {
+    let mut out = String::new();
+
+    csv::write_row(&mut out, &["kind", "name", "count"]);
+
+    for tag in &stats.tags {
+        csv::write_row(&mut out, &["tag", &tag.tag, &tag.count.to_string()]);
+    }
+
+    for folder in &stats.untagged {
+        csv::write_row(
+            &mut out,
+            &[
+                "untagged",
+                &folder.folder.display().to_string(),
+                &folder.count.to_string(),
+            ],
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats() -> anyhow::Result<Stats> {
+        let initial = include_str!("test_data/initial.xml");
+
+        let mut meta = FolderMetadata::from_xmp_str(initial)?;
+        meta.get_or_insert_item("bd3.wav")
+            .add_keyword("Drums|Kick|Acoustic");
+
+        let files = ["bd1.wav", "bd2.wav", "bd3.wav", "bd4.wav", "bd5.wav"].map(String::from);
+
+        let mut builder = StatsBuilder::default();
+        builder.add_folder(Path::new("Drums"), Some(&meta), &files);
+        builder.add_folder(Path::new("Empty"), None, &files[..1]);
+
+        Ok(builder.build())
+    }
+
+    #[test]
+    fn should_render_plain() -> anyhow::Result<()> {
+        let expected = "\
+Files: 6 (3 tagged, 3 untagged)
+
+Tags:
+  2  Creator|17cupsofcoffee
+  1  Drums|Kick
+  1  Drums|Kick|Acoustic
+
+Hierarchy:
+├── Creator (2)
+│   └── 17cupsofcoffee (2)
+└── Drums (2)
+    └── Kick (2)
+        └── Acoustic (1)
+
+Untagged files:
+  2  Drums
+  1  Empty
+";
+
+        pretty_assertions::assert_eq!(render(&stats()?, StatsFormat::Plain)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_build_hierarchy() -> anyhow::Result<()> {
+        let mut meta = FolderMetadata::new()?;
+
+        let item = meta.get_or_insert_item("bd1.wav");
+        item.add_keyword("Drums|Snare");
+        item.add_keyword("Drums Loop");
+        item.add_keyword("Drums|Kick");
+
+        let mut builder = StatsBuilder::default();
+        builder.add_folder(Path::new("Drums"), Some(&meta), &[]);
+
+        let hierarchy = builder.build().hierarchy;
+
+        let names = |nodes: &[TagNode]| nodes.iter().map(|n| n.name.clone()).collect::<Vec<_>>();
+
+        // `Drums Loop` sorts between `Drums` and `Drums|Kick` as a string.
+        assert_eq!(names(&hierarchy), ["Drums", "Drums Loop"]);
+        assert_eq!(names(&hierarchy[0].children), ["Kick", "Snare"]);
+        assert_eq!(hierarchy[0].count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_render_csv() -> anyhow::Result<()> {
+        let expected = "\
+kind,name,count
+tag,Creator|17cupsofcoffee,2
+tag,Drums|Kick,1
+tag,Drums|Kick|Acoustic,1
+untagged,Drums,2
+untagged,Empty,1
+";
+
+        pretty_assertions::assert_eq!(render(&stats()?, StatsFormat::Csv)?, expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::bail;
+use livemeta::Tag;
+use regex::Regex;
+use tracing::warn;
+
+/// Tags containing `{name}` placeholders, which are filled in from a regex's
+/// captures when it is matched against a filename.
+///
+/// Placeholders can refer to named captures, or to numbered captures (e.g. `{1}`).
+#[derive(Debug)]
+pub struct TagTemplates {
+    regex: Regex,
+    templates: Vec<Tag>,
+}
+
+impl TagTemplates {
+    /// Creates a set of templates, checking that every placeholder refers to a
+    /// capture in the regex.
+    pub fn new(regex: Regex, templates: Vec<Tag>) -> anyhow::Result<TagTemplates> {
+        for template in &templates {
+            for name in placeholders(template.as_str()) {
+                let exists = match name.parse::<usize>() {
+                    Ok(index) => index < regex.captures_len(),
+                    Err(_) => regex.capture_names().flatten().any(|n| n == name),
+                };
+
+                if !exists {
+                    bail!("{template} refers to {{{name}}}, which is not captured by the regex");
+                }
+            }
+        }
+
+        Ok(TagTemplates { regex, templates })
+    }
+
+    /// Fills in the templates for a filename.
+    ///
+    /// If the regex doesn't match, no tags are returned. Tags whose placeholders
+    /// didn't capture anything, or which aren't valid once filled in, are skipped.
+    pub fn tags_for(&self, file: &str) -> Vec<Tag> {
+        let Some(captures) = self.regex.captures(file) else {
+            return Vec::new();
+        };
+
+        let mut tags = Vec::new();
+
+        for template in &self.templates {
+            let value = expand(template.as_str(), |name| match name.parse::<usize>() {
+                Ok(index) => captures.get(index).map(|m| m.as_str()),
+                Err(_) => captures.name(name).map(|m| m.as_str()),
+            });
+
+            let Some(value) = value else {
+                warn!(
+                    "Skipping {} for {}, as part of it wasn't captured",
+                    template, file
+                );
+                continue;
+            };
+
+            match Tag::parse(&value) {
+                Ok(tag) if !tags.contains(&tag) => tags.push(tag),
+                Ok(_) => {}
+                Err(e) => warn!("Skipping {} for {}: {}", template, file, e),
+            }
+        }
+
+        tags
+    }
+}
+
+/// Returns the names of the `{name}` placeholders in a template.
+pub fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|part| {
+        let (name, _) = part.split_once('}')?;
+        is_placeholder_name(name).then_some(name)
+    })
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Fills in a template's placeholders using `lookup`, returning `None` if any of
+/// them have no value (or an empty one).
+pub fn expand<'a>(template: &str, lookup: impl Fn(&str) -> Option<&'a str>) -> Option<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+
+        let after = &rest[start + 1..];
+
+        match after.split_once('}') {
+            Some((name, remaining)) if is_placeholder_name(name) => {
+                let value = lookup(name).filter(|v| !v.is_empty())?;
+
+                out.push_str(value);
+                rest = remaining;
+            }
+
+            // Anything that doesn't look like a placeholder is left as-is.
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates(regex: &str, templates: &[&str]) -> anyhow::Result<TagTemplates> {
+        let templates = templates
+            .iter()
+            .map(|t| Tag::parse(t))
+            .collect::<livemeta::Result<_>>()?;
+
+        TagTemplates::new(Regex::new(regex)?, templates)
+    }
+
+    fn tags_for(templates: &TagTemplates, file: &str) -> Vec<String> {
+        templates
+            .tags_for(file)
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn should_fill_in_captures() -> anyhow::Result<()> {
+        let templates = templates(
+            r"_(?<bpm>\d+)bpm_(?<key>[A-G][a-z#]*)?",
+            &["BPM|{bpm}", "Key|{key}", "Loop", "Raw|{0}"],
+        )?;
+
+        assert_eq!(
+            tags_for(&templates, "Loop_124bpm_Amin.wav"),
+            ["BPM|124", "Key|Amin", "Loop", "Raw|_124bpm_Amin"]
+        );
+
+        assert_eq!(
+            tags_for(&templates, "Loop_90bpm_.wav"),
+            ["BPM|90", "Loop", "Raw|_90bpm_"]
+        );
+
+        assert!(tags_for(&templates, "Kick.wav").is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_leave_other_braces_alone() -> anyhow::Result<()> {
+        let templates = templates(r"(\d+)", &["Take {1} {of 2}"])?;
+
+        assert_eq!(tags_for(&templates, "take3.wav"), ["Take 3 {of 2}"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_unknown_captures() {
+        assert!(templates(r"(?<bpm>\d+)", &["Key|{key}"]).is_err());
+        assert!(templates(r"(\d+)", &["Take {2}"]).is_err());
+    }
+}